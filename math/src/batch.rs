@@ -0,0 +1,190 @@
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+use crate::error::MapError;
+use crate::map::{generate_map, Biome, Map, MapSettings};
+
+/// One line of the batch report.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MapSummary {
+    pub seed: u64,
+    pub regions: usize,
+    /// Share of the area above `SEA_LEVEL`.
+    pub land_ratio: f32,
+    pub landmasses: usize,
+    /// Share of the area of every biome, missing biomes included.
+    pub biomes: BTreeMap<String, f32>,
+    pub generation_ms: f64,
+}
+
+impl MapSummary {
+    pub fn new(seed: u64, map: &Map, generation_time: Duration) -> Self {
+        let statistics = map.statistics();
+        let area = (statistics.land_area + statistics.water_area).max(f32::MIN_POSITIVE);
+        Self {
+            seed,
+            regions: statistics.regions,
+            land_ratio: statistics.land_ratio,
+            landmasses: statistics.landmasses,
+            biomes: statistics
+                .biomes
+                .into_iter()
+                .map(|(name, biome)| (name, biome.area / area))
+                .collect(),
+            generation_ms: generation_time.as_secs_f64() * 1000.,
+        }
+    }
+}
+
+/// Generates the map `settings` gives for every seed and hands it to `each` before dropping it.
+/// Maps are spread over one thread per core, the results come back in the order of `seeds`,
+/// with the error of every seed that failed.
+pub fn generate_batch<S, R, F>(
+    seeds: &[u64],
+    settings: S,
+    each: F,
+) -> Vec<Result<(MapSummary, R), MapError>>
+where
+    S: Fn(u64) -> MapSettings + Sync,
+    R: Send,
+    F: Fn(&Map, &MapSummary) -> R + Sync,
+{
+    let workers = thread::available_parallelism()
+        .map(|count| count.get())
+        .unwrap_or(1)
+        .min(seeds.len());
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new(Vec::with_capacity(seeds.len()));
+    thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                let Some(&seed) = seeds.get(i) else {
+                    break;
+                };
+                let start = Instant::now();
+                let result = generate_map(settings(seed)).map(|map| {
+                    let summary = MapSummary::new(seed, &map, start.elapsed());
+                    let result = each(&map, &summary);
+                    (summary, result)
+                });
+                results.lock().unwrap().push((i, result));
+            });
+        }
+    });
+    let mut results = results.into_inner().unwrap();
+    results.sort_by_key(|(i, _)| *i);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+/// A header line, then a line per map with a column per biome.
+pub fn write_csv<W: Write>(summaries: &[MapSummary], mut writer: W) -> io::Result<()> {
+    let biomes: Vec<String> = Biome::ALL
+        .iter()
+        .map(|biome| format!("{:?}", biome))
+        .collect();
+    writeln!(
+        writer,
+        "seed,regions,land_ratio,landmasses,{},generation_ms",
+        biomes.join(",")
+    )?;
+    for summary in summaries {
+        write!(
+            writer,
+            "{},{},{:.4},{},",
+            summary.seed, summary.regions, summary.land_ratio, summary.landmasses
+        )?;
+        for biome in &biomes {
+            write!(writer, "{:.4},", summary.biomes[biome])?;
+        }
+        writeln!(writer, "{:.1}", summary.generation_ms)?;
+    }
+    Ok(())
+}
+
+pub fn write_json<W: Write>(summaries: &[MapSummary], writer: W) -> io::Result<()> {
+    serde_json::to_writer_pretty(writer, summaries)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod batch_tests {
+    use super::*;
+    use crate::map::{DistanceFn, ReshapingFn};
+    use crate::Boundary;
+    use nalgebra_glm::Vec2;
+
+    fn settings(seed: u64) -> MapSettings {
+        let boundary = Boundary::from_top_left(Vec2::new(0., 24.), 24., 24.);
+        MapSettings::new(boundary, seed, DistanceFn::Euclidean, ReshapingFn::Linear)
+            .with_site_count(300)
+    }
+
+    #[test]
+    fn test_batch_keeps_seed_order() {
+        let seeds = [9, 3, 5, 3];
+        let results: Vec<_> = generate_batch(&seeds, settings, |map, summary| {
+            (map.get_settings().seed, summary.regions)
+        })
+        .into_iter()
+        .map(Result::unwrap)
+        .collect();
+        let order: Vec<u64> = results.iter().map(|(summary, _)| summary.seed).collect();
+        assert_eq!(order, seeds);
+        for (summary, (seed, regions)) in &results {
+            assert_eq!(summary.seed, *seed);
+            assert_eq!(summary.regions, *regions);
+            assert!((0. ..=1.).contains(&summary.land_ratio));
+            let total: f32 = summary.biomes.values().sum();
+            assert!((total - 1.).abs() < 1e-3);
+        }
+        assert_eq!(results[1].0.land_ratio, results[3].0.land_ratio);
+        assert_eq!(results[1].0.landmasses, results[3].0.landmasses);
+    }
+
+    #[test]
+    fn test_reports() {
+        let results = generate_batch(&[1, 2], settings, |_, _| ());
+        let summaries: Vec<MapSummary> = results
+            .into_iter()
+            .map(|result| result.unwrap().0)
+            .collect();
+        let mut csv = vec![];
+        write_csv(&summaries, &mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("seed,regions,land_ratio,landmasses,Abyss,"));
+        for line in &lines {
+            assert_eq!(line.split(',').count(), 5 + Biome::ALL.len());
+        }
+        assert!(lines[2].starts_with("2,"));
+
+        let mut json = vec![];
+        write_json(&summaries, &mut json).unwrap();
+        let value: serde_json::Value = serde_json::from_slice(&json).unwrap();
+        assert_eq!(value[0]["seed"], 1);
+        assert!(value[1]["biomes"]["Ocean"].is_number());
+    }
+
+    #[test]
+    fn test_failed_seeds() {
+        let flat = |seed| {
+            let boundary = Boundary::from_top_left(Vec2::new(0., 24.), 24., 0.);
+            MapSettings {
+                boundary,
+                ..settings(seed)
+            }
+        };
+        let results = generate_batch(&[1, 2], flat, |_, _| ());
+        assert!(results
+            .iter()
+            .all(|result| matches!(result, Err(MapError::DegenerateSites))));
+    }
+}
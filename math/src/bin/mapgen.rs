@@ -0,0 +1,441 @@
+//! Generates a map without opening a window and writes it to the requested outputs.
+//!
+//! ```text
+//! mapgen --seed 7 --sites 2000 --reshape Smooth -o island.vmap -o island.png -o island.glb
+//! mapgen --config island.json --seed 8
+//! mapgen --seeds 0..200 -o maps/{seed}.png --summary maps/summary.csv
+//! ```
+
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use math::batch::{generate_batch, write_csv, write_json, MapSummary};
+use math::export::geojson::{save_geojson, GeoJsonOptions};
+use math::export::heightmap::{Heightmap, HeightmapOptions};
+use math::export::mesh::{MeshOptions, TerrainMesh};
+use math::export::raster::{self, RasterOptions};
+use math::export::svg::{write_svg, SvgOptions};
+use math::export::tiled::{save_tmx, TiledOptions};
+use math::map::{DistanceFn, Map, MapSettings, ReshapingFn, Wrap};
+use math::save::save_map;
+use math::Boundary;
+use nalgebra_glm::Vec2;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+
+const USAGE: &str = "\
+usage: mapgen [options] -o <file>...
+
+options:
+  --config <file>        JSON file with any of the options below, flags override it
+  --seed <n>             terrain seed [12345]
+  --seeds <list>         one map per seed, e.g. 0..100 or 1,5,9 or 0..10,42, generated in
+                         parallel. Outputs need {seed} in their name to tell them apart
+  --site-seed <n>        seed of the site layout [the terrain seed]
+  --width <units>        [64]
+  --height <units>       [64]
+  --sites <n>            number of regions [one per unit of area]
+  --distance <name>      island shape, e.g. Euclidean, Diagonal, Manhattan [Diagonal]
+  --reshape <name>       elevation curve, e.g. Flat, Linear, Smooth, Archipelago [Flat]
+  --wrap <name>          None, Horizontal or Both [None]
+  --image-size <px>      long side of the .png and .r16 outputs [1024]
+  --height-scale <units> height of the mesh outputs [20]
+  -o, --output <file>    output picked by extension, can be repeated:
+                         .json .vmap      map file, JSON or binary
+                         .svg .png        flat picture
+                         .height.png .r16 16 bit heightmap
+                         .normal.png      normal map
+                         .geojson .obj .glb .tmx
+                         .stats.txt .stats.json map statistics
+                         {seed} in the name is replaced by the seed of the map
+  --summary <file>       .csv or .json report of every map, can be repeated
+  --validate             check every map with Map::validate, invalid maps are reported
+                         instead of written
+  -h, --help";
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct Config {
+    seed: u64,
+    seeds: Option<String>,
+    site_seed: Option<u64>,
+    width: f32,
+    height: f32,
+    sites: Option<usize>,
+    distance: DistanceFn,
+    reshape: ReshapingFn,
+    wrap: Wrap,
+    image_size: u32,
+    height_scale: f32,
+    outputs: Vec<PathBuf>,
+    summary: Vec<PathBuf>,
+    validate: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            seed: 12345,
+            seeds: None,
+            site_seed: None,
+            width: 64.,
+            height: 64.,
+            sites: None,
+            distance: DistanceFn::Diagonal,
+            reshape: ReshapingFn::Flat,
+            wrap: Wrap::None,
+            image_size: 1024,
+            height_scale: 20.,
+            outputs: vec![],
+            summary: vec![],
+            validate: false,
+        }
+    }
+}
+
+impl Config {
+    /// Reads `--config` first, wherever it is, then applies the other flags on top of it.
+    fn from_args(args: &[String]) -> Result<Self, String> {
+        let mut config = Config::default();
+        if let Some(at) = args.iter().position(|arg| arg == "--config") {
+            let path = args.get(at + 1).ok_or("--config needs a file")?;
+            let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+            config = serde_json::from_str(&text).map_err(|e| format!("{}: {}", path, e))?;
+        }
+        let mut args = args.iter();
+        while let Some(flag) = args.next() {
+            let mut value = || {
+                args.next()
+                    .map(String::as_str)
+                    .ok_or(format!("{} needs a value", flag))
+            };
+            match flag.as_str() {
+                "--config" => {
+                    value()?;
+                }
+                "--seed" => config.seed = parse(flag, value()?)?,
+                "--seeds" => config.seeds = Some(value()?.to_string()),
+                "--site-seed" => config.site_seed = Some(parse(flag, value()?)?),
+                "--width" => config.width = parse(flag, value()?)?,
+                "--height" => config.height = parse(flag, value()?)?,
+                "--sites" => config.sites = Some(parse(flag, value()?)?),
+                "--distance" => config.distance = parse_name(flag, value()?)?,
+                "--reshape" => config.reshape = parse_name(flag, value()?)?,
+                "--wrap" => config.wrap = parse_name(flag, value()?)?,
+                "--image-size" => config.image_size = parse(flag, value()?)?,
+                "--height-scale" => config.height_scale = parse(flag, value()?)?,
+                "-o" | "--output" => config.outputs.push(PathBuf::from(value()?)),
+                "--summary" => config.summary.push(PathBuf::from(value()?)),
+                "--validate" => config.validate = true,
+                _ => return Err(format!("unknown option {}", flag)),
+            }
+        }
+        if !(config.width > 0. && config.height > 0.) {
+            return Err("the boundary must have a positive size".to_string());
+        }
+        if config.seeds()?.len() > 1 {
+            let path = config
+                .outputs
+                .iter()
+                .find(|path| !path.to_string_lossy().contains("{seed}"));
+            if let Some(path) = path {
+                return Err(format!(
+                    "{} would be overwritten by every map, add {{seed}} to its name",
+                    path.display()
+                ));
+            }
+        }
+        Ok(config)
+    }
+
+    /// `seeds` as a list of ranges and single seeds, or just `seed`.
+    fn seeds(&self) -> Result<Vec<u64>, String> {
+        let Some(list) = &self.seeds else {
+            return Ok(vec![self.seed]);
+        };
+        let mut seeds = vec![];
+        for item in list.split(',').map(str::trim) {
+            match item.split_once("..") {
+                Some((start, end)) => {
+                    let start: u64 = parse("--seeds", start)?;
+                    let end: u64 = parse("--seeds", end)?;
+                    seeds.extend(start..end);
+                }
+                None => seeds.push(parse("--seeds", item)?),
+            }
+        }
+        if seeds.is_empty() {
+            return Err(format!("no seed in {}", list));
+        }
+        Ok(seeds)
+    }
+
+    /// `image_size` along the long side of the boundary.
+    fn image_dimensions(&self) -> (u32, u32) {
+        let scale = self.image_size as f32 / self.width.max(self.height);
+        let side = |length: f32| ((length * scale).round() as u32).max(1);
+        (side(self.width), side(self.height))
+    }
+
+    fn map_settings(&self, seed: u64) -> MapSettings {
+        let boundary = Boundary::from_top_left(
+            Vec2::new(-self.width / 2., self.height / 2.),
+            self.width,
+            self.height,
+        );
+        let mut settings =
+            MapSettings::new(boundary, seed, self.distance, self.reshape).with_wrap(self.wrap);
+        settings.site_seed = self.site_seed.unwrap_or(seed);
+        // the default layout only covers the viewer's 64 x 64 boundary
+        let area = self.width * self.height;
+        settings.site_count = self
+            .sites
+            .or_else(|| (self.width != 64. || self.height != 64.).then(|| area as usize));
+        settings
+    }
+}
+
+fn parse<T: std::str::FromStr>(flag: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid value {} for {}", value, flag))
+}
+
+/// Enum variants by the names they have in the config file.
+fn parse_name<T: DeserializeOwned>(flag: &str, value: &str) -> Result<T, String> {
+    serde_json::from_value(serde_json::Value::String(value.to_string()))
+        .map_err(|_| format!("unknown name {} for {}", value, flag))
+}
+
+fn write_output(map: &Map, config: &Config, path: &Path) -> Result<(), Box<dyn Error>> {
+    let name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    let size = config.image_size;
+    let (width, height) = config.image_dimensions();
+    let heightmap = || {
+        let options = HeightmapOptions {
+            width,
+            height,
+            ..Default::default()
+        };
+        Heightmap::sample(map, &options)
+    };
+    let extension = name.rsplit('.').next().unwrap_or_default();
+    if name.ends_with(".stats.txt") {
+        std::fs::write(path, map.statistics().to_string())?;
+    } else if name.ends_with(".stats.json") {
+        let mut writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(&mut writer, &map.statistics())?;
+        writer.flush()?;
+    } else if name.ends_with(".height.png") {
+        heightmap().save_png(path)?;
+    } else if name.ends_with(".normal.png") {
+        heightmap().save_normal_map(size as f32 / 16., path)?;
+    } else {
+        match extension {
+            "json" | "vmap" => save_map(map, path)?,
+            "svg" => {
+                let mut writer = BufWriter::new(File::create(path)?);
+                write_svg(map, &SvgOptions::default(), &mut writer)?;
+                writer.flush()?;
+            }
+            "png" => {
+                let options = RasterOptions {
+                    width,
+                    height,
+                    ..Default::default()
+                };
+                raster::save_png(map, &options, path)?;
+            }
+            "r16" => heightmap().save_r16(path)?,
+            "geojson" => save_geojson(map, &GeoJsonOptions::default(), path)?,
+            "obj" | "glb" => {
+                let options = MeshOptions {
+                    height_scale: config.height_scale,
+                    ..Default::default()
+                };
+                let mesh = TerrainMesh::build(map, &options);
+                if extension == "obj" {
+                    mesh.save_obj(path)?;
+                } else {
+                    mesh.save_glb(path)?;
+                }
+            }
+            "tmx" => save_tmx(map, &TiledOptions::default(), path)?,
+            _ => return Err(format!("no output format for the extension of {}", name).into()),
+        }
+    }
+    Ok(())
+}
+
+fn write_summary(summaries: &[MapSummary], path: &Path) -> Result<(), Box<dyn Error>> {
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    let mut writer = BufWriter::new(File::create(path)?);
+    match extension.as_str() {
+        "csv" => write_csv(summaries, &mut writer)?,
+        "json" => write_json(summaries, &mut writer)?,
+        _ => return Err("the summary must be a .csv or .json file".into()),
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == "-h" || arg == "--help") {
+        println!("{}", USAGE);
+        return ExitCode::SUCCESS;
+    }
+    let config = match Config::from_args(&args) {
+        Ok(config) => config,
+        Err(error) => {
+            eprintln!("mapgen: {}\n\n{}", error, USAGE);
+            return ExitCode::from(2);
+        }
+    };
+    if config.outputs.is_empty() && config.summary.is_empty() {
+        eprintln!("mapgen: nothing to write, add an --output\n\n{}", USAGE);
+        return ExitCode::from(2);
+    }
+
+    // checked by from_args
+    let seeds = config.seeds().unwrap();
+    let results = generate_batch(
+        &seeds,
+        |seed| config.map_settings(seed),
+        |map, summary| {
+            if config.validate {
+                let report = map.validate();
+                if !report.is_valid() {
+                    eprintln!("mapgen: seed {}: {}", summary.seed, report);
+                    return true;
+                }
+            }
+            let mut failed = false;
+            for path in &config.outputs {
+                let path = PathBuf::from(
+                    path.to_string_lossy()
+                        .replace("{seed}", &summary.seed.to_string()),
+                );
+                match write_output(map, &config, &path) {
+                    Ok(()) => println!("wrote {}", path.display()),
+                    Err(error) => {
+                        eprintln!("mapgen: {}: {}", path.display(), error);
+                        failed = true;
+                    }
+                }
+            }
+            failed
+        },
+    );
+    let mut failed = false;
+    let mut summaries: Vec<MapSummary> = vec![];
+    for (seed, result) in seeds.iter().zip(results) {
+        match result {
+            Ok((summary, written)) => {
+                failed |= written;
+                summaries.push(summary);
+            }
+            Err(error) => {
+                eprintln!("mapgen: seed {}: {}", seed, error);
+                failed = true;
+            }
+        }
+    }
+    for path in &config.summary {
+        match write_summary(&summaries, path) {
+            Ok(()) => println!("wrote {}", path.display()),
+            Err(error) => {
+                eprintln!("mapgen: {}: {}", path.display(), error);
+                failed = true;
+            }
+        }
+    }
+    if failed {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+#[cfg(test)]
+mod mapgen_tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn test_flags() {
+        let config = Config::from_args(&args(
+            "--seed 7 --width 100 --distance Euclidean --wrap Both -o a.png --output b.glb --validate",
+        ))
+        .unwrap();
+        assert_eq!(config.seed, 7);
+        assert_eq!(config.distance, DistanceFn::Euclidean);
+        assert_eq!(config.wrap, Wrap::Both);
+        assert!(config.validate);
+        assert_eq!(
+            config.outputs,
+            [PathBuf::from("a.png"), PathBuf::from("b.glb")]
+        );
+        let settings = config.map_settings(config.seed);
+        assert_eq!(settings.site_seed, 7);
+        assert_eq!(settings.site_count, Some(6400));
+        assert_eq!(settings.boundary.top_left(), Vec2::new(-50., 32.));
+        assert_eq!(config.image_dimensions(), (1024, 655));
+
+        let config = Config::from_args(&[]).unwrap();
+        assert_eq!(config.map_settings(config.seed).site_count, None);
+        assert!(Config::from_args(&args("--seed")).is_err());
+        assert!(Config::from_args(&args("--reshape Wobbly")).is_err());
+        assert!(Config::from_args(&args("--frobnicate 3")).is_err());
+    }
+
+    #[test]
+    fn test_seeds() {
+        let config = Config::from_args(&args("--seeds 3..6,10,0..2 -o {seed}.png")).unwrap();
+        assert_eq!(config.seeds().unwrap(), [3, 4, 5, 10, 0, 1]);
+        let config = Config::from_args(&args("--seed 8 --site-seed 2")).unwrap();
+        assert_eq!(config.seeds().unwrap(), [8]);
+        assert_eq!(config.map_settings(9).site_seed, 2);
+        assert!(Config::from_args(&args("--seeds 1..4 -o map.png")).is_err());
+        assert!(Config::from_args(&args("--seeds 1..x")).is_err());
+        assert!(Config::from_args(&args("--seeds 5..5")).is_err());
+    }
+
+    #[test]
+    fn test_config_file() {
+        let name = format!("mapgen_test_config_file_{}.json", std::process::id());
+        let path = std::env::temp_dir().join(name);
+        std::fs::write(
+            &path,
+            r#"{"seed": 3, "sites": 500, "reshape": "Smooth", "outputs": ["c.svg"]}"#,
+        )
+        .unwrap();
+        let config = Config::from_args(&args(&format!(
+            "--seed 4 --config {} -o d.tmx",
+            path.display()
+        )))
+        .unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(config.seed, 4);
+        assert_eq!(config.sites, Some(500));
+        assert_eq!(config.reshape, ReshapingFn::Smooth);
+        assert_eq!(
+            config.outputs,
+            [PathBuf::from("c.svg"), PathBuf::from("d.tmx")]
+        );
+    }
+}
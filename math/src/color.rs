@@ -92,6 +92,23 @@ impl RGB {
     pub fn new_f32(r: f32, g: f32, b: f32) -> Self {
         Self { r, g, b }
     }
+
+    pub fn to_bytes(self) -> [u8; 3] {
+        let byte = |c: f32| (c.clamp(0., 1.) * 255.).round() as u8;
+        [byte(self.r), byte(self.g), byte(self.b)]
+    }
+
+    /// `#rrggbb`
+    pub fn to_hex(self) -> String {
+        let [r, g, b] = self.to_bytes();
+        format!("#{:02x}{:02x}{:02x}", r, g, b)
+    }
+}
+
+impl From<[f32; 3]> for RGB {
+    fn from(color: [f32; 3]) -> Self {
+        Self::new_f32(color[0], color[1], color[2])
+    }
 }
 
 impl From<RGB> for [f32; 3] {
@@ -105,6 +122,26 @@ impl From<RGB> for (f32, f32, f32) {
     }
 }
 
+/// Blue to red gradient for values in `[0, 1]`.
+pub fn heat_map(value: f32) -> RGB {
+    const STOPS: [(f32, f32, f32); 5] = [
+        (0.19, 0.21, 0.58),
+        (0.17, 0.63, 0.80),
+        (0.55, 0.80, 0.33),
+        (0.99, 0.80, 0.20),
+        (0.84, 0.15, 0.16),
+    ];
+    let t = value.clamp(0., 1.) * (STOPS.len() - 1) as f32;
+    let i = (t as usize).min(STOPS.len() - 2);
+    let f = t - i as f32;
+    let (a, b) = (STOPS[i], STOPS[i + 1]);
+    RGB::new_f32(
+        a.0 + (b.0 - a.0) * f,
+        a.1 + (b.1 - a.1) * f,
+        a.2 + (b.2 - a.2) * f,
+    )
+}
+
 pub struct RGBA {
     pub r: f32,
     pub g: f32,
@@ -138,10 +175,10 @@ impl HSL {
     }
 
     pub fn to_rgb(self) -> RGB {
+        let h = self.h.rem_euclid(360);
         let c = (1. - (2. * self.l - 1.).abs()) * self.s;
-        let x = c * (1. - ((self.h - 60) % 2 - 1).abs() as f32);
+        let x = c * (1. - ((h as f32 / 60.) % 2. - 1.).abs());
         let m = self.l - c / 2.;
-        let h = self.h % 360;
         let (r, g, b) = if h < 60 {
             (c, x, 0.)
         } else if h < 120 {
@@ -158,3 +195,41 @@ impl HSL {
         RGB::new_f32(r + m, g + m, b + m)
     }
 }
+
+#[cfg(test)]
+mod color_tests {
+    use super::*;
+
+    fn channels(color: RGB) -> [f32; 3] {
+        color.into()
+    }
+
+    #[test]
+    fn test_hsl_to_rgb() {
+        let close = |a: [f32; 3], b: [f32; 3]| a.iter().zip(&b).all(|(a, b)| (a - b).abs() < 1e-3);
+        assert!(close(channels(HSL::new(0, 1., 0.5).to_rgb()), [1., 0., 0.]));
+        assert!(close(
+            channels(HSL::new(60, 1., 0.5).to_rgb()),
+            [1., 1., 0.]
+        ));
+        assert!(close(
+            channels(HSL::new(210, 1., 0.5).to_rgb()),
+            [0., 0.5, 1.]
+        ));
+        assert!(close(
+            channels(HSL::new(360, 1., 0.5).to_rgb()),
+            [1., 0., 0.]
+        ));
+
+        // the kingdom colors, every hue has to come out different and in range
+        let colors: Vec<[f32; 3]> = (0..6)
+            .map(|owner| channels(HSL::new((owner * 137 % 360) as u16, 0.55, 0.55).to_rgb()))
+            .collect();
+        for (i, a) in colors.iter().enumerate() {
+            assert!(a.iter().all(|c| (0. ..=1.).contains(c)));
+            for b in &colors[i + 1..] {
+                assert!(!close(*a, *b));
+            }
+        }
+    }
+}
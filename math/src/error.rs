@@ -0,0 +1,43 @@
+use std::fmt;
+
+use spade::InsertionError;
+
+use crate::validate::ValidationReport;
+
+/// Why a map could not be generated.
+#[derive(Debug)]
+pub enum MapError {
+    /// A site can't go in the triangulation, e.g. a coordinate is not finite.
+    Triangulation(InsertionError),
+    /// Fewer than three sites, or all of them on one line, so there is no diagram to build.
+    DegenerateSites,
+    /// Fewer than four cells along a wrapping axis, regions would border themselves or the
+    /// same neighbor on both sides of the edge.
+    TooNarrow,
+    /// Stopped by its `CancellationToken`.
+    Cancelled,
+    /// Rejected by `Map::validate`, see `generate_valid_map`, or by `WorldSettings::validate`.
+    Invalid(ValidationReport),
+}
+
+impl fmt::Display for MapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MapError::Triangulation(error) => write!(f, "a site can't be triangulated: {}", error),
+            MapError::DegenerateSites => write!(f, "the sites don't span an area"),
+            MapError::TooNarrow => {
+                write!(f, "the map is too narrow along a wrapping axis")
+            }
+            MapError::Cancelled => write!(f, "map generation was cancelled"),
+            MapError::Invalid(report) => write!(f, "the map is invalid: {}", report),
+        }
+    }
+}
+
+impl std::error::Error for MapError {}
+
+impl From<InsertionError> for MapError {
+    fn from(error: InsertionError) -> Self {
+        MapError::Triangulation(error)
+    }
+}
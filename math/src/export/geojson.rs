@@ -0,0 +1,221 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use nalgebra_glm::Vec2;
+use serde_json::{json, Value};
+
+use crate::color::RGB;
+use crate::map::Map;
+use crate::stats::{clip_polygon, polygon_area};
+use crate::Boundary;
+
+/// `x' = a x + b y + c` and `y' = d x + e y + f`, stored as `[a, b, c, d, e, f]` like a GDAL
+/// geotransform.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GeoTransform(pub [f64; 6]);
+
+impl GeoTransform {
+    pub fn identity() -> Self {
+        GeoTransform([1., 0., 0., 0., 1., 0.])
+    }
+
+    /// Maps the bottom left corner of the boundary to `min` and the top right one to `max`, e.g.
+    /// `[-180, -90]` and `[180, 90]` to lay a map over the whole globe in degrees.
+    pub fn fit(boundary: &Boundary, min: [f64; 2], max: [f64; 2]) -> Self {
+        let origin = boundary.bottom_left();
+        let sx = (max[0] - min[0]) / boundary.width() as f64;
+        let sy = (max[1] - min[1]) / boundary.height() as f64;
+        GeoTransform([
+            sx,
+            0.,
+            min[0] - origin.x as f64 * sx,
+            0.,
+            sy,
+            min[1] - origin.y as f64 * sy,
+        ])
+    }
+
+    pub fn apply(&self, point: Vec2) -> [f64; 2] {
+        let [a, b, c, d, e, f] = self.0;
+        let (x, y) = (point.x as f64, point.y as f64);
+        [a * x + b * y + c, d * x + e * y + f]
+    }
+}
+
+impl Default for GeoTransform {
+    fn default() -> Self {
+        Self::identity()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct GeoJsonOptions {
+    pub transform: GeoTransform,
+    pub regions: bool,
+    pub sites: bool,
+}
+
+impl Default for GeoJsonOptions {
+    fn default() -> Self {
+        Self {
+            transform: GeoTransform::identity(),
+            regions: true,
+            sites: false,
+        }
+    }
+}
+
+/// A FeatureCollection with a Polygon per region followed by a Point per site. Every feature has
+/// a `kind` property telling the two apart and the `index` of its region. Regions are clipped to
+/// the boundary, the ones left without an area have no Polygon.
+pub fn map_to_geojson(map: &Map, options: &GeoJsonOptions) -> Value {
+    let transform = &options.transform;
+    let (min, max) = map.clip_rect();
+    let mut features = vec![];
+    if options.regions {
+        for (i, region) in map.get_regions().iter().enumerate() {
+            let polygon = clip_polygon(region.polygon(), min, max);
+            if polygon.len() < 3 || polygon_area(&polygon) == 0. {
+                continue;
+            }
+            features.push(json!({
+                "type": "Feature",
+                "geometry": {
+                    "type": "Polygon",
+                    "coordinates": [ring(polygon, transform)],
+                },
+                "properties": {
+                    "kind": "region",
+                    "index": i,
+                    "elevation": region.elevation,
+                    "moisture": region.moisture,
+                    "biome": format!("{:?}", region.biome()),
+                    "color": RGB::from(region.color).to_hex(),
+                },
+            }));
+        }
+    }
+    if options.sites {
+        for (i, region) in map.get_regions().iter().enumerate() {
+            features.push(json!({
+                "type": "Feature",
+                "geometry": {
+                    "type": "Point",
+                    "coordinates": transform.apply(region.site),
+                },
+                "properties": {
+                    "kind": "site",
+                    "index": i,
+                },
+            }));
+        }
+    }
+    json!({
+        "type": "FeatureCollection",
+        "features": features,
+    })
+}
+
+pub fn write_geojson<W: Write>(map: &Map, options: &GeoJsonOptions, writer: W) -> io::Result<()> {
+    serde_json::to_writer(writer, &map_to_geojson(map, options))?;
+    Ok(())
+}
+
+pub fn save_geojson(map: &Map, options: &GeoJsonOptions, path: impl AsRef<Path>) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    write_geojson(map, options, &mut writer)?;
+    writer.flush()
+}
+
+/// Closed and counterclockwise after the transform, as RFC 7946 asks for exterior rings.
+fn ring(polygon: Vec<Vec2>, transform: &GeoTransform) -> Vec<[f64; 2]> {
+    let mut ring: Vec<[f64; 2]> = polygon.into_iter().map(|p| transform.apply(p)).collect();
+    let area: f64 = (0..ring.len())
+        .map(|k| {
+            let (a, b) = (ring[k], ring[(k + 1) % ring.len()]);
+            a[0] * b[1] - b[0] * a[1]
+        })
+        .sum();
+    if area < 0. {
+        ring.reverse();
+    }
+    if let Some(&first) = ring.first() {
+        ring.push(first);
+    }
+    ring
+}
+
+#[cfg(test)]
+mod geojson_tests {
+    use super::*;
+    use crate::map::{test_map, Wrap};
+
+    #[test]
+    fn test_fit_transform() {
+        let boundary = Boundary::from_top_left(Vec2::new(-32.0, 32.0), 64., 64.);
+        let transform = GeoTransform::fit(&boundary, [-180., -90.], [180., 90.]);
+        assert_eq!(transform.apply(boundary.bottom_left()), [-180., -90.]);
+        assert_eq!(transform.apply(boundary.top_right()), [180., 90.]);
+        assert_eq!(transform.apply(Vec2::new(0., 0.)), [0., 0.]);
+    }
+
+    #[test]
+    fn test_feature_collection() {
+        let map = test_map(Wrap::None);
+        let boundary = map.get_boundary().clone();
+        let options = GeoJsonOptions {
+            transform: GeoTransform::fit(&boundary, [0., 0.], [10., 10.]),
+            sites: true,
+            ..Default::default()
+        };
+        let mut bytes = vec![];
+        write_geojson(&map, &options, &mut bytes).unwrap();
+        let value: Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(value["type"], "FeatureCollection");
+        let features = value["features"].as_array().unwrap();
+        let count = map.get_regions().len();
+        let polygons = features.len() - count;
+        assert!(polygons > count / 2 && polygons < count);
+
+        let mut last = None;
+        for feature in &features[..polygons] {
+            assert_eq!(feature["geometry"]["type"], "Polygon");
+            let index = feature["properties"]["index"].as_u64().unwrap();
+            assert!(last < Some(index));
+            last = Some(index);
+            let ring = feature["geometry"]["coordinates"][0].as_array().unwrap();
+            assert!(ring.len() >= 4);
+            assert_eq!(ring.first(), ring.last());
+            let points: Vec<[f64; 2]> = ring
+                .iter()
+                .map(|p| [p[0].as_f64().unwrap(), p[1].as_f64().unwrap()])
+                .collect();
+            assert!(points
+                .iter()
+                .flatten()
+                .all(|&c| (-1e-4..=10. + 1e-4).contains(&c)));
+            let area: f64 = points
+                .windows(2)
+                .map(|w| w[0][0] * w[1][1] - w[1][0] * w[0][1])
+                .sum();
+            assert!(area > 0.);
+        }
+        let index = map.region_at(Vec2::zeros()).unwrap();
+        let feature = features[..polygons]
+            .iter()
+            .find(|feature| feature["properties"]["index"] == index)
+            .unwrap();
+        let region = &map.get_regions()[index];
+        let properties = &feature["properties"];
+        assert_eq!(properties["kind"], "region");
+        assert_eq!(properties["biome"], format!("{:?}", region.biome()));
+        assert_eq!(properties["color"], RGB::from(region.color).to_hex());
+        assert_eq!(features[polygons + index]["geometry"]["type"], "Point");
+        assert_eq!(features[polygons + index]["properties"]["kind"], "site");
+        let site = &features[polygons + index]["geometry"]["coordinates"];
+        let expected = options.transform.apply(region.site);
+        assert!((site[0].as_f64().unwrap() - expected[0]).abs() < 1e-9);
+        assert!((site[1].as_f64().unwrap() - expected[1]).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,228 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use image::{ImageBuffer, ImageResult, Luma, Rgb, RgbImage};
+use nalgebra_glm::Vec3;
+
+use crate::map::{Map, SEA_LEVEL};
+
+#[derive(Debug, Clone)]
+pub struct HeightmapOptions {
+    pub width: u32,
+    pub height: u32,
+    /// Elevation written as 0.
+    pub min_elevation: f32,
+    /// Elevation written as the largest value.
+    pub max_elevation: f32,
+    /// Output height in `[0, 1]` the map's `SEA_LEVEL` lands on, for engines that expect their
+    /// water plane somewhere else than halfway.
+    pub sea_level: f32,
+}
+
+impl Default for HeightmapOptions {
+    fn default() -> Self {
+        Self {
+            width: 513,
+            height: 513,
+            min_elevation: 0.,
+            max_elevation: 1.,
+            sea_level: 0.5,
+        }
+    }
+}
+
+impl HeightmapOptions {
+    /// Maps `[min_elevation, SEA_LEVEL]` to `[0, sea_level]` and `[SEA_LEVEL, max_elevation]` to
+    /// `[sea_level, 1]`. When `min_elevation` or `max_elevation` isn't past `SEA_LEVEL`, the
+    /// elevations on that side all go to 0 or 1.
+    pub fn normalize(&self, elevation: f32) -> f32 {
+        let height = if elevation < SEA_LEVEL {
+            let range = (SEA_LEVEL - self.min_elevation).max(f32::EPSILON);
+            self.sea_level * (1. - (SEA_LEVEL - elevation) / range)
+        } else {
+            let range = (self.max_elevation - SEA_LEVEL).max(f32::EPSILON);
+            self.sea_level + (1. - self.sea_level) * (elevation - SEA_LEVEL) / range
+        };
+        height.clamp(0., 1.)
+    }
+}
+
+/// Elevation sampled on a regular grid, row by row from the top left corner, in `[0, 1]`.
+#[derive(Debug, Clone)]
+pub struct Heightmap {
+    width: u32,
+    height: u32,
+    values: Vec<f32>,
+}
+
+impl Heightmap {
+    pub fn sample(map: &Map, options: &HeightmapOptions) -> Self {
+        let values = map
+            .elevation_grid(options.width as usize, options.height as usize)
+            .into_iter()
+            .map(|elevation| options.normalize(elevation))
+            .collect();
+        Self {
+            width: options.width,
+            height: options.height,
+            values,
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn values(&self) -> &[f32] {
+        &self.values
+    }
+
+    pub fn get(&self, x: u32, y: u32) -> f32 {
+        self.values[(y * self.width + x) as usize]
+    }
+
+    pub fn to_u16(&self) -> Vec<u16> {
+        self.values
+            .iter()
+            .map(|&value| (value * u16::MAX as f32).round() as u16)
+            .collect()
+    }
+
+    pub fn to_image(&self) -> ImageBuffer<Luma<u16>, Vec<u16>> {
+        ImageBuffer::from_raw(self.width, self.height, self.to_u16()).unwrap()
+    }
+
+    /// 16 bit grayscale PNG.
+    pub fn save_png(&self, path: impl AsRef<Path>) -> ImageResult<()> {
+        self.to_image()
+            .save_with_format(path, image::ImageFormat::Png)
+    }
+
+    /// Headerless little endian 16 bit values, the `.r16` layout terrain importers read.
+    pub fn write_r16<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        for value in self.to_u16() {
+            writer.write_all(&value.to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    pub fn save_r16(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_r16(&mut writer)?;
+        writer.flush()
+    }
+
+    /// Tangent space normals with green pointing up the image. `strength` is how many pixels the
+    /// whole height range spans, higher values give steeper slopes.
+    pub fn normal_map(&self, strength: f32) -> RgbImage {
+        let (width, height) = (self.width as i64, self.height as i64);
+        let at =
+            |x: i64, y: i64| self.get(x.clamp(0, width - 1) as u32, y.clamp(0, height - 1) as u32);
+        RgbImage::from_fn(self.width, self.height, |x, y| {
+            let (x, y) = (x as i64, y as i64);
+            let dx = (at(x + 1, y) - at(x - 1, y)) / 2.;
+            let dy = (at(x, y - 1) - at(x, y + 1)) / 2.;
+            let normal = Vec3::new(-dx * strength, -dy * strength, 1.).normalize();
+            let encode = |c: f32| ((c * 0.5 + 0.5) * 255.).round() as u8;
+            Rgb([encode(normal.x), encode(normal.y), encode(normal.z)])
+        })
+    }
+
+    pub fn save_normal_map(&self, strength: f32, path: impl AsRef<Path>) -> ImageResult<()> {
+        self.normal_map(strength)
+            .save_with_format(path, image::ImageFormat::Png)
+    }
+}
+
+#[cfg(test)]
+mod heightmap_tests {
+    use super::*;
+    use crate::map::{test_map, Wrap};
+
+    #[test]
+    fn test_normalize_keeps_sea_level() {
+        let options = HeightmapOptions {
+            min_elevation: 0.2,
+            max_elevation: 0.9,
+            sea_level: 0.25,
+            ..Default::default()
+        };
+        assert_eq!(options.normalize(SEA_LEVEL), 0.25);
+        assert_eq!(options.normalize(0.2), 0.);
+        assert_eq!(options.normalize(0.0), 0.);
+        assert_eq!(options.normalize(0.9), 1.);
+        assert!(options.normalize(0.6) > 0.25);
+    }
+
+    #[test]
+    fn test_normalize_empty_ranges() {
+        for (min_elevation, max_elevation) in [(SEA_LEVEL, SEA_LEVEL), (0.8, 0.1)] {
+            let options = HeightmapOptions {
+                min_elevation,
+                max_elevation,
+                sea_level: 0.25,
+                ..Default::default()
+            };
+            assert_eq!(options.normalize(SEA_LEVEL), 0.25);
+            assert_eq!(options.normalize(0.3), 0.);
+            assert_eq!(options.normalize(0.7), 1.);
+        }
+    }
+
+    #[test]
+    fn test_r16_and_png() {
+        let options = HeightmapOptions {
+            width: 33,
+            height: 17,
+            ..Default::default()
+        };
+        let heightmap = Heightmap::sample(&test_map(Wrap::None), &options);
+        let values = heightmap.to_u16();
+        assert_eq!(values.len(), 33 * 17);
+        assert!(values.iter().any(|&v| v < u16::MAX / 2));
+        assert!(values.iter().any(|&v| v > u16::MAX / 2));
+
+        let mut raw = vec![];
+        heightmap.write_r16(&mut raw).unwrap();
+        assert_eq!(raw.len(), 33 * 17 * 2);
+        assert_eq!(u16::from_le_bytes([raw[2], raw[3]]), values[1]);
+
+        let name = format!("math_test_r16_and_png_{}.png", std::process::id());
+        let path = std::env::temp_dir().join(name);
+        heightmap.save_png(&path).unwrap();
+        let loaded = image::open(&path).unwrap().into_luma16();
+        assert_eq!(loaded.dimensions(), (33, 17));
+        assert_eq!(loaded.into_raw(), values);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_normal_map() {
+        let flat = Heightmap {
+            width: 4,
+            height: 4,
+            values: vec![0.3; 16],
+        };
+        assert!(flat
+            .normal_map(10.)
+            .pixels()
+            .all(|p| *p == Rgb([128, 128, 255])));
+
+        // rising to the right, normals lean left
+        let ramp = Heightmap {
+            width: 4,
+            height: 4,
+            values: (0..16).map(|i| (i % 4) as f32 / 3.).collect(),
+        };
+        let normals = ramp.normal_map(4.);
+        let Rgb([r, g, b]) = *normals.get_pixel(1, 1);
+        assert!(r < 128);
+        assert_eq!(g, 128);
+        assert!(b > 128);
+    }
+}
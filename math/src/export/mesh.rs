@@ -0,0 +1,481 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use nalgebra_glm::Vec2;
+use serde_json::{json, Value};
+
+use crate::map::{Biome, Map, SEA_LEVEL};
+use crate::stats::clip_polygon;
+
+#[derive(Debug, Clone)]
+pub struct MeshOptions {
+    /// Height of an elevation of 1 above `SEA_LEVEL`, in map units.
+    pub height_scale: f32,
+    /// Puts the water regions in their own flat `water` node at height 0 instead of lifting
+    /// them with the land.
+    pub flatten_water: bool,
+}
+
+impl Default for MeshOptions {
+    fn default() -> Self {
+        Self {
+            height_scale: 20.,
+            flatten_water: true,
+        }
+    }
+}
+
+/// Triangles sharing a biome material. Vertices are not shared between regions so every region
+/// keeps its own color.
+#[derive(Debug, Clone, Default)]
+pub struct MeshGroup {
+    pub positions: Vec<[f32; 3]>,
+    pub colors: Vec<[f32; 3]>,
+    pub indices: Vec<u32>,
+}
+
+/// A named node of the scene, `land` or `water`, with a group per biome.
+#[derive(Debug, Clone)]
+pub struct MeshPart {
+    pub name: &'static str,
+    pub groups: BTreeMap<Biome, MeshGroup>,
+}
+
+/// Y up and right handed like glTF: map x is x, the height is y and map y is -z.
+#[derive(Debug, Clone)]
+pub struct TerrainMesh {
+    pub parts: Vec<MeshPart>,
+}
+
+impl TerrainMesh {
+    /// Every region becomes a fan around its site, clipped to the boundary. The corners take the
+    /// elevation interpolated between the sites around them so neighboring regions meet without
+    /// cracks.
+    pub fn build(map: &Map, options: &MeshOptions) -> Self {
+        let lift = |elevation: f32| (elevation - SEA_LEVEL) * options.height_scale;
+        let (min, max) = map.clip_rect();
+        let mut land = MeshPart {
+            name: "land",
+            groups: BTreeMap::new(),
+        };
+        let mut water = MeshPart {
+            name: "water",
+            groups: BTreeMap::new(),
+        };
+        for region in map.get_regions() {
+            let mut corners = clip_polygon(region.polygon(), min, max);
+            if corners.len() < 3 || signed_area(&corners) == 0. {
+                continue;
+            }
+            if signed_area(&corners) < 0. {
+                corners.reverse();
+            }
+            // the cells of the sites outside the boundary keep the sliver inside it, fanned from
+            // its middle
+            let site = region.site;
+            let center = if site.x >= min.x && site.y >= min.y && site.x <= max.x && site.y <= max.y
+            {
+                site
+            } else {
+                corners
+                    .iter()
+                    .fold(Vec2::zeros(), |sum, corner| sum + corner)
+                    / corners.len() as f32
+            };
+            let flat = options.flatten_water && region.is_water();
+            let part = if flat { &mut water } else { &mut land };
+            let group = part.groups.entry(region.biome()).or_default();
+            let height = |point: Vec2, fallback: f32| {
+                if flat {
+                    0.
+                } else {
+                    lift(map.elevation_at(point).unwrap_or(fallback))
+                }
+            };
+            let first = group.positions.len() as u32;
+            group
+                .positions
+                .push(to_space(center, height(center, region.elevation)));
+            for &corner in &corners {
+                group
+                    .positions
+                    .push(to_space(corner, height(corner, region.elevation)));
+            }
+            group.colors.resize(group.positions.len(), region.color);
+            let count = corners.len() as u32;
+            for k in 0..count {
+                group
+                    .indices
+                    .extend([first, first + 1 + k, first + 1 + (k + 1) % count]);
+            }
+        }
+        let parts = [land, water]
+            .into_iter()
+            .filter(|part| !part.groups.is_empty())
+            .collect();
+        Self { parts }
+    }
+
+    pub fn triangle_count(&self) -> usize {
+        self.groups()
+            .map(|(_, _, group)| group.indices.len() / 3)
+            .sum()
+    }
+
+    fn groups(&self) -> impl Iterator<Item = (&'static str, Biome, &MeshGroup)> {
+        self.parts.iter().flat_map(|part| {
+            part.groups
+                .iter()
+                .map(move |(&biome, group)| (part.name, biome, group))
+        })
+    }
+
+    fn biomes(&self) -> Vec<Biome> {
+        let mut biomes: Vec<Biome> = self.groups().map(|(_, biome, _)| biome).collect();
+        biomes.sort();
+        biomes.dedup();
+        biomes
+    }
+
+    /// Wavefront OBJ with an object per part and a `usemtl` per biome. Vertex colors follow
+    /// the positions, the extension Blender and most viewers read.
+    pub fn write_obj<W: Write>(&self, mut writer: W, mtl_file: &str) -> io::Result<()> {
+        writeln!(writer, "mtllib {}", mtl_file)?;
+        let mut offset = 1;
+        for part in &self.parts {
+            writeln!(writer, "o {}", part.name)?;
+            for (biome, group) in &part.groups {
+                for (p, c) in group.positions.iter().zip(&group.colors) {
+                    writeln!(
+                        writer,
+                        "v {} {} {} {:.4} {:.4} {:.4}",
+                        p[0], p[1], p[2], c[0], c[1], c[2]
+                    )?;
+                }
+                writeln!(writer, "usemtl {:?}", biome)?;
+                for triangle in group.indices.chunks_exact(3) {
+                    writeln!(
+                        writer,
+                        "f {} {} {}",
+                        triangle[0] + offset,
+                        triangle[1] + offset,
+                        triangle[2] + offset
+                    )?;
+                }
+                offset += group.positions.len() as u32;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn write_mtl<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        for biome in self.biomes() {
+            let color = biome.to_color();
+            writeln!(writer, "newmtl {:?}", biome)?;
+            writeln!(writer, "Kd {:.4} {:.4} {:.4}", color.r, color.g, color.b)?;
+            writeln!(writer, "Ka 0 0 0")?;
+            writeln!(writer, "Ks 0 0 0")?;
+            writeln!(writer, "illum 1")?;
+            writeln!(writer)?;
+        }
+        Ok(())
+    }
+
+    /// Writes `path` and the material library next to it with the `mtl` extension.
+    pub fn save_obj(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let path = path.as_ref();
+        let mtl_path = path.with_extension("mtl");
+        let mtl_file = mtl_path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or("map.mtl");
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_obj(&mut writer, mtl_file)?;
+        writer.flush()?;
+        let mut writer = BufWriter::new(File::create(&mtl_path)?);
+        self.write_mtl(&mut writer)?;
+        writer.flush()
+    }
+
+    /// Binary glTF 2.0: a node and mesh per part, a primitive per biome with its own material,
+    /// and the region colors as `COLOR_0`.
+    pub fn write_glb<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        let biomes = self.biomes();
+        let mut buffer: Vec<u8> = vec![];
+        let mut views = vec![];
+        let mut accessors = vec![];
+        let mut push_view = |buffer: &mut Vec<u8>, bytes: Vec<u8>, target: u32| {
+            views.push(json!({
+                "buffer": 0,
+                "byteOffset": buffer.len(),
+                "byteLength": bytes.len(),
+                "target": target,
+            }));
+            buffer.extend(bytes);
+            views.len() - 1
+        };
+
+        let mut meshes = vec![];
+        for part in &self.parts {
+            let mut primitives = vec![];
+            for (biome, group) in &part.groups {
+                let positions = floats(&group.positions);
+                let (min, max) = bounds(&group.positions);
+                let view = push_view(&mut buffer, positions, 34962);
+                accessors.push(json!({
+                    "bufferView": view,
+                    "componentType": 5126,
+                    "count": group.positions.len(),
+                    "type": "VEC3",
+                    "min": min,
+                    "max": max,
+                }));
+                let position = accessors.len() - 1;
+
+                let linear: Vec<[f32; 3]> = group.colors.iter().map(|&c| to_linear(c)).collect();
+                let view = push_view(&mut buffer, floats(&linear), 34962);
+                accessors.push(json!({
+                    "bufferView": view,
+                    "componentType": 5126,
+                    "count": linear.len(),
+                    "type": "VEC3",
+                }));
+                let color = accessors.len() - 1;
+
+                let indices = group.indices.iter().flat_map(|i| i.to_le_bytes()).collect();
+                let view = push_view(&mut buffer, indices, 34963);
+                accessors.push(json!({
+                    "bufferView": view,
+                    "componentType": 5125,
+                    "count": group.indices.len(),
+                    "type": "SCALAR",
+                }));
+                let indices = accessors.len() - 1;
+
+                primitives.push(json!({
+                    "attributes": { "POSITION": position, "COLOR_0": color },
+                    "indices": indices,
+                    "material": biomes.binary_search(biome).unwrap(),
+                }));
+            }
+            meshes.push(json!({ "name": part.name, "primitives": primitives }));
+        }
+
+        let materials: Vec<Value> = biomes
+            .iter()
+            .map(|biome| {
+                let color = biome.to_color();
+                let [r, g, b] = to_linear([color.r, color.g, color.b]);
+                json!({
+                    "name": format!("{:?}", biome),
+                    "pbrMetallicRoughness": {
+                        "baseColorFactor": [r, g, b, 1.0],
+                        "metallicFactor": 0.0,
+                        "roughnessFactor": 1.0,
+                    },
+                })
+            })
+            .collect();
+        let nodes: Vec<Value> = self
+            .parts
+            .iter()
+            .enumerate()
+            .map(|(i, part)| json!({ "name": part.name, "mesh": i }))
+            .collect();
+        let document = json!({
+            "asset": { "version": "2.0", "generator": "math" },
+            "scene": 0,
+            "scenes": [{ "nodes": (0..nodes.len()).collect::<Vec<_>>() }],
+            "nodes": nodes,
+            "meshes": meshes,
+            "materials": materials,
+            "accessors": accessors,
+            "bufferViews": views,
+            "buffers": [{ "byteLength": buffer.len() }],
+        });
+
+        let mut json = serde_json::to_vec(&document)?;
+        json.resize(json.len().next_multiple_of(4), b' ');
+        buffer.resize(buffer.len().next_multiple_of(4), 0);
+        let length = 12 + 8 + json.len() + 8 + buffer.len();
+        writer.write_all(b"glTF")?;
+        writer.write_all(&2u32.to_le_bytes())?;
+        writer.write_all(&(length as u32).to_le_bytes())?;
+        writer.write_all(&(json.len() as u32).to_le_bytes())?;
+        writer.write_all(b"JSON")?;
+        writer.write_all(&json)?;
+        writer.write_all(&(buffer.len() as u32).to_le_bytes())?;
+        writer.write_all(b"BIN\0")?;
+        writer.write_all(&buffer)
+    }
+
+    pub fn save_glb(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_glb(&mut writer)?;
+        writer.flush()
+    }
+}
+
+fn to_space(point: Vec2, height: f32) -> [f32; 3] {
+    [point.x, height, -point.y]
+}
+
+fn signed_area(polygon: &[Vec2]) -> f32 {
+    (0..polygon.len())
+        .map(|k| {
+            let (a, b) = (polygon[k], polygon[(k + 1) % polygon.len()]);
+            a.x * b.y - b.x * a.y
+        })
+        .sum()
+}
+
+fn floats(values: &[[f32; 3]]) -> Vec<u8> {
+    values
+        .iter()
+        .flatten()
+        .flat_map(|value| value.to_le_bytes())
+        .collect()
+}
+
+fn bounds(positions: &[[f32; 3]]) -> ([f32; 3], [f32; 3]) {
+    positions.iter().fold(
+        ([f32::MAX; 3], [f32::MIN; 3]),
+        |(mut min, mut max), position| {
+            for axis in 0..3 {
+                min[axis] = min[axis].min(position[axis]);
+                max[axis] = max[axis].max(position[axis]);
+            }
+            (min, max)
+        },
+    )
+}
+
+/// glTF colors are linear while the map colors are sRGB.
+fn to_linear(color: [f32; 3]) -> [f32; 3] {
+    color.map(|c| {
+        if c <= 0.04045 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    })
+}
+
+#[cfg(test)]
+mod mesh_tests {
+    use super::*;
+    use crate::map::{test_map, Wrap};
+
+    #[test]
+    fn test_water_is_flat() {
+        let map = test_map(Wrap::None);
+        let mesh = TerrainMesh::build(&map, &MeshOptions::default());
+        let names: Vec<&str> = mesh.parts.iter().map(|part| part.name).collect();
+        assert_eq!(names, ["land", "water"]);
+        let water = &mesh.parts[1];
+        assert!(water
+            .groups
+            .values()
+            .flat_map(|group| &group.positions)
+            .all(|p| p[1] == 0.));
+        let (min, max) = map.clip_rect();
+        let corners: usize = map
+            .get_regions()
+            .iter()
+            .map(|r| clip_polygon(r.polygon(), min, max))
+            .filter(|corners| corners.len() >= 3 && signed_area(corners) != 0.)
+            .map(|corners| corners.len())
+            .sum();
+        assert_eq!(mesh.triangle_count(), corners);
+
+        let merged = TerrainMesh::build(
+            &map,
+            &MeshOptions {
+                flatten_water: false,
+                ..Default::default()
+            },
+        );
+        assert_eq!(merged.parts.len(), 1);
+        assert!(merged.parts[0]
+            .groups
+            .values()
+            .flat_map(|group| &group.positions)
+            .any(|p| p[1] < 0.));
+    }
+
+    #[test]
+    fn test_stays_in_the_boundary() {
+        let map = test_map(Wrap::None);
+        let boundary = map.get_boundary();
+        let (min, max) = (boundary.bottom_left(), boundary.top_right());
+        let mesh = TerrainMesh::build(&map, &MeshOptions::default());
+        for (_, _, group) in mesh.groups() {
+            for p in &group.positions {
+                assert!(p[0] >= min.x && p[0] <= max.x, "{:?}", p);
+                assert!(-p[2] >= min.y && -p[2] <= max.y, "{:?}", p);
+            }
+        }
+    }
+
+    #[test]
+    fn test_obj() {
+        let mesh = TerrainMesh::build(&test_map(Wrap::None), &MeshOptions::default());
+        let mut obj = vec![];
+        mesh.write_obj(&mut obj, "island.mtl").unwrap();
+        let obj = String::from_utf8(obj).unwrap();
+        let vertices = obj.lines().filter(|line| line.starts_with("v ")).count();
+        assert!(obj.starts_with("mtllib island.mtl\n"));
+        assert_eq!(
+            obj.lines().filter(|line| line.starts_with("f ")).count(),
+            mesh.triangle_count()
+        );
+        let largest = obj
+            .lines()
+            .filter(|line| line.starts_with("f "))
+            .flat_map(|line| line[2..].split(' ').map(|i| i.parse::<usize>().unwrap()))
+            .max()
+            .unwrap();
+        assert_eq!(largest, vertices);
+
+        let mut mtl = vec![];
+        mesh.write_mtl(&mut mtl).unwrap();
+        let mtl = String::from_utf8(mtl).unwrap();
+        for line in obj.lines().filter(|line| line.starts_with("usemtl ")) {
+            assert!(mtl.contains(&format!("newmtl {}\n", &line[7..])));
+        }
+    }
+
+    #[test]
+    fn test_glb() {
+        let mesh = TerrainMesh::build(&test_map(Wrap::None), &MeshOptions::default());
+        let mut glb = vec![];
+        mesh.write_glb(&mut glb).unwrap();
+        let word = |at: usize| u32::from_le_bytes(glb[at..at + 4].try_into().unwrap()) as usize;
+        assert_eq!(&glb[..4], b"glTF");
+        assert_eq!(word(4), 2);
+        assert_eq!(word(8), glb.len());
+        let json_length = word(12);
+        assert_eq!(&glb[16..20], b"JSON");
+        assert_eq!(json_length % 4, 0);
+        let document: Value = serde_json::from_slice(&glb[20..20 + json_length]).unwrap();
+        let bin = 20 + json_length;
+        assert_eq!(&glb[bin + 4..bin + 8], b"BIN\0");
+        assert_eq!(word(bin), glb.len() - bin - 8);
+
+        assert_eq!(document["nodes"][1]["name"], "water");
+        let views = document["bufferViews"].as_array().unwrap();
+        let last = views.last().unwrap();
+        let end = last["byteOffset"].as_u64().unwrap() + last["byteLength"].as_u64().unwrap();
+        assert_eq!(document["buffers"][0]["byteLength"], end);
+        let triangles: u64 = document["accessors"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter(|accessor| accessor["type"] == "SCALAR")
+            .map(|accessor| accessor["count"].as_u64().unwrap() / 3)
+            .sum();
+        assert_eq!(triangles as usize, mesh.triangle_count());
+    }
+}
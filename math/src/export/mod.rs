@@ -0,0 +1,55 @@
+use nalgebra_glm::Vec2;
+
+use crate::Boundary;
+
+pub mod geojson;
+pub mod heightmap;
+pub mod mesh;
+pub mod raster;
+pub mod svg;
+pub mod tiled;
+
+/// Image coordinates for a boundary drawn `scale` pixels per unit, origin at the top left corner
+/// and y pointing down.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct ImageFrame {
+    top_left: Vec2,
+    scale: Vec2,
+    width: f32,
+    height: f32,
+}
+
+impl ImageFrame {
+    pub(crate) fn new(boundary: &Boundary, scale: f32) -> Self {
+        Self::fit(
+            boundary,
+            boundary.width() * scale,
+            boundary.height() * scale,
+        )
+    }
+
+    /// Stretches the boundary over an image of `width` x `height`.
+    pub(crate) fn fit(boundary: &Boundary, width: f32, height: f32) -> Self {
+        Self {
+            top_left: boundary.top_left(),
+            scale: Vec2::new(width / boundary.width(), height / boundary.height()),
+            width,
+            height,
+        }
+    }
+
+    pub(crate) fn to_image(self, point: Vec2) -> Vec2 {
+        Vec2::new(
+            (point.x - self.top_left.x) * self.scale.x,
+            (self.top_left.y - point.y) * self.scale.y,
+        )
+    }
+
+    pub(crate) fn width(&self) -> f32 {
+        self.width
+    }
+
+    pub(crate) fn height(&self) -> f32 {
+        self.height
+    }
+}
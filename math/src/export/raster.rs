@@ -0,0 +1,207 @@
+use std::path::Path;
+
+use image::{ImageResult, Rgba, RgbaImage};
+use nalgebra_glm::Vec2;
+
+use crate::color::{PresetColors, RGB};
+use crate::export::ImageFrame;
+use crate::map::Map;
+
+#[derive(Debug, Clone)]
+pub struct RasterOptions {
+    pub width: u32,
+    pub height: u32,
+    /// Fill with the flat `Biome::to_color` instead of the shaded colors the viewer uses.
+    pub biome_palette: bool,
+    pub edges: bool,
+    pub sites: bool,
+    /// In pixels.
+    pub site_radius: f32,
+    pub background: [u8; 4],
+}
+
+impl Default for RasterOptions {
+    fn default() -> Self {
+        Self {
+            width: 1024,
+            height: 1024,
+            biome_palette: false,
+            edges: false,
+            sites: false,
+            site_radius: 1.5,
+            background: [0, 0, 0, 0],
+        }
+    }
+}
+
+/// Renders the boundary of the map stretched over the whole image, without any GPU.
+pub fn render_map(map: &Map, options: &RasterOptions) -> RgbaImage {
+    let mut image = RgbaImage::from_pixel(options.width, options.height, Rgba(options.background));
+    let frame = ImageFrame::fit(
+        map.get_boundary(),
+        options.width as f32,
+        options.height as f32,
+    );
+    for region in map.get_regions() {
+        let color = if options.biome_palette {
+            region.biome().to_color()
+        } else {
+            RGB::from(region.color)
+        };
+        let polygon: Vec<Vec2> = region
+            .polygon()
+            .into_iter()
+            .map(|corner| frame.to_image(corner))
+            .collect();
+        fill_polygon(&mut image, &polygon, pixel(color));
+    }
+    if options.edges {
+        let black = pixel(RGB::from(<[f32; 3]>::from(PresetColors::BLACK)));
+        for (i, region) in map.get_regions().iter().enumerate() {
+            for (k, &neighbor) in region.neighbors.iter().enumerate() {
+                if neighbor > i {
+                    let (a, b) = region.edge(k);
+                    draw_line(&mut image, frame.to_image(a), frame.to_image(b), black);
+                }
+            }
+        }
+    }
+    if options.sites {
+        let red = pixel(RGB::from(<[f32; 3]>::from(PresetColors::RED)));
+        for region in map.get_regions() {
+            fill_disk(
+                &mut image,
+                frame.to_image(region.site),
+                options.site_radius,
+                red,
+            );
+        }
+    }
+    image
+}
+
+pub fn save_png(map: &Map, options: &RasterOptions, path: impl AsRef<Path>) -> ImageResult<()> {
+    render_map(map, options).save_with_format(path, image::ImageFormat::Png)
+}
+
+fn pixel(color: RGB) -> Rgba<u8> {
+    let [r, g, b] = color.to_bytes();
+    Rgba([r, g, b, 255])
+}
+
+/// Even-odd scanline fill sampled at the pixel centers. Edges are half open, so two polygons
+/// sharing an edge never both cover a pixel and never leave a gap between them.
+fn fill_polygon(image: &mut RgbaImage, polygon: &[Vec2], color: Rgba<u8>) {
+    if polygon.len() < 3 {
+        return;
+    }
+    let (min_y, max_y) = polygon.iter().fold((f32::MAX, f32::MIN), |(min, max), p| {
+        (min.min(p.y), max.max(p.y))
+    });
+    let first_row = (min_y - 0.5).ceil().max(0.) as u32;
+    let last_row = ((max_y - 0.5).floor() as i64).min(image.height() as i64 - 1);
+    let mut crossings = vec![];
+    for row in first_row as i64..=last_row {
+        let y = row as f32 + 0.5;
+        crossings.clear();
+        for k in 0..polygon.len() {
+            let (a, b) = (polygon[k], polygon[(k + 1) % polygon.len()]);
+            if (a.y <= y && y < b.y) || (b.y <= y && y < a.y) {
+                crossings.push(a.x + (y - a.y) / (b.y - a.y) * (b.x - a.x));
+            }
+        }
+        crossings.sort_by(f32::total_cmp);
+        for span in crossings.chunks_exact(2) {
+            let start = (span[0] - 0.5).ceil().max(0.) as i64;
+            let end = ((span[1] - 0.5).ceil() as i64).min(image.width() as i64);
+            for column in start..end {
+                image.put_pixel(column as u32, row as u32, color);
+            }
+        }
+    }
+}
+
+fn draw_line(image: &mut RgbaImage, from: Vec2, to: Vec2, color: Rgba<u8>) {
+    let steps = (to - from).abs().max().ceil().max(1.) as usize;
+    for step in 0..=steps {
+        let point = from + (to - from) * (step as f32 / steps as f32);
+        put(image, point.x.floor() as i64, point.y.floor() as i64, color);
+    }
+}
+
+fn fill_disk(image: &mut RgbaImage, center: Vec2, radius: f32, color: Rgba<u8>) {
+    let reach = radius.ceil() as i64;
+    let (cx, cy) = (center.x.floor() as i64, center.y.floor() as i64);
+    for y in cy - reach..=cy + reach {
+        for x in cx - reach..=cx + reach {
+            let offset = Vec2::new(x as f32 + 0.5, y as f32 + 0.5) - center;
+            if offset.norm() <= radius {
+                put(image, x, y, color);
+            }
+        }
+    }
+}
+
+fn put(image: &mut RgbaImage, x: i64, y: i64, color: Rgba<u8>) {
+    if x >= 0 && y >= 0 && x < image.width() as i64 && y < image.height() as i64 {
+        image.put_pixel(x as u32, y as u32, color);
+    }
+}
+
+#[cfg(test)]
+mod raster_tests {
+    use super::*;
+    use crate::map::{test_map, Wrap};
+
+    #[test]
+    fn test_regions_cover_the_image() {
+        let map = test_map(Wrap::None);
+        let options = RasterOptions {
+            width: 300,
+            height: 200,
+            ..Default::default()
+        };
+        let image = render_map(&map, &options);
+        assert_eq!(image.dimensions(), (300, 200));
+        assert!(image.pixels().all(|pixel| pixel.0[3] == 255));
+
+        let frame = ImageFrame::fit(map.get_boundary(), 300., 200.);
+        let region = map.region_at(Vec2::new(4.2, -7.9)).unwrap();
+        let site = frame.to_image(map.get_regions()[region].site);
+        let expected = pixel(RGB::from(map.get_regions()[region].color));
+        assert_eq!(*image.get_pixel(site.x as u32, site.y as u32), expected);
+    }
+
+    #[test]
+    fn test_overlays() {
+        let map = test_map(Wrap::None);
+        let plain = render_map(&map, &RasterOptions::default());
+        let options = RasterOptions {
+            edges: true,
+            sites: true,
+            ..Default::default()
+        };
+        let overlaid = render_map(&map, &options);
+        let black = Rgba([0, 0, 0, 255]);
+        let red = Rgba([255, 0, 0, 255]);
+        assert!(!plain.pixels().any(|&pixel| pixel == black || pixel == red));
+        assert!(overlaid.pixels().filter(|&&pixel| pixel == black).count() > 10_000);
+        assert!(overlaid.pixels().filter(|&&pixel| pixel == red).count() > 4_000);
+    }
+
+    #[test]
+    fn test_save_png() {
+        // the process id keeps concurrent test runs apart
+        let name = format!("math_test_save_png_{}.png", std::process::id());
+        let path = std::env::temp_dir().join(name);
+        let options = RasterOptions {
+            width: 64,
+            height: 32,
+            ..Default::default()
+        };
+        save_png(&test_map(Wrap::None), &options, &path).unwrap();
+        let loaded = image::open(&path).unwrap();
+        assert_eq!((loaded.width(), loaded.height()), (64, 32));
+        std::fs::remove_file(path).unwrap();
+    }
+}
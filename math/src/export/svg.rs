@@ -0,0 +1,187 @@
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+
+use crate::color::{PresetColors, RGB};
+use crate::export::ImageFrame;
+use crate::map::{Biome, Map, RegionIndex};
+
+#[derive(Debug, Clone)]
+pub struct SvgOptions {
+    /// Pixels per map unit.
+    pub scale: f32,
+    /// Fill with the flat `Biome::to_color` instead of the shaded colors the viewer uses.
+    pub biome_palette: bool,
+    /// Voronoi edges and the boundary rectangle, like the viewer's wire layer.
+    pub edges: bool,
+    pub sites: bool,
+    pub edge_width: f32,
+    pub site_radius: f32,
+}
+
+impl Default for SvgOptions {
+    fn default() -> Self {
+        Self {
+            scale: 10.,
+            biome_palette: false,
+            edges: true,
+            sites: false,
+            edge_width: 0.5,
+            site_radius: 1.5,
+        }
+    }
+}
+
+pub fn map_to_svg(map: &Map, options: &SvgOptions) -> String {
+    let mut bytes = vec![];
+    write_svg(map, options, &mut bytes).unwrap();
+    String::from_utf8(bytes).unwrap()
+}
+
+/// One layer per biome holding its regions, then the optional edge and site layers. Layers are
+/// `<g>` elements marked the way Inkscape expects, and every region polygon carries its index
+/// in `data-region`.
+pub fn write_svg<W: Write>(map: &Map, options: &SvgOptions, mut writer: W) -> io::Result<()> {
+    let frame = ImageFrame::new(map.get_boundary(), options.scale);
+    let regions = map.get_regions();
+    writeln!(
+        writer,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" xmlns:inkscape="http://www.inkscape.org/namespaces/inkscape" width="{w:.0}" height="{h:.0}" viewBox="0 0 {w:.2} {h:.2}">"#,
+        w = frame.width(),
+        h = frame.height()
+    )?;
+
+    let mut biomes: BTreeMap<Biome, Vec<RegionIndex>> = BTreeMap::new();
+    for (i, region) in regions.iter().enumerate() {
+        biomes.entry(region.biome()).or_default().push(i);
+    }
+    for (biome, members) in &biomes {
+        writeln!(
+            writer,
+            r#"  <g id="biome-{b:?}" inkscape:groupmode="layer" inkscape:label="{b:?}" data-biome="{b:?}">"#,
+            b = biome
+        )?;
+        for &i in members {
+            let region = &regions[i];
+            let color = if options.biome_palette {
+                biome.to_color()
+            } else {
+                RGB::from(region.color)
+            };
+            let points: Vec<String> = region
+                .polygon()
+                .into_iter()
+                .map(|corner| {
+                    let p = frame.to_image(corner);
+                    format!("{:.2},{:.2}", p.x, p.y)
+                })
+                .collect();
+            writeln!(
+                writer,
+                r#"    <polygon points="{}" fill="{}" data-region="{}" data-elevation="{:.3}" data-moisture="{:.3}"/>"#,
+                points.join(" "),
+                color.to_hex(),
+                i,
+                region.elevation,
+                region.moisture
+            )?;
+        }
+        writeln!(writer, "  </g>")?;
+    }
+
+    if options.edges {
+        let black = RGB::from(<[f32; 3]>::from(PresetColors::BLACK)).to_hex();
+        let teal = RGB::from(<[f32; 3]>::from(PresetColors::TEAL)).to_hex();
+        writeln!(
+            writer,
+            r#"  <g id="edges" inkscape:groupmode="layer" inkscape:label="Edges" fill="none" stroke="{}" stroke-width="{}">"#,
+            black, options.edge_width
+        )?;
+        for (i, region) in regions.iter().enumerate() {
+            for (k, &neighbor) in region.neighbors.iter().enumerate() {
+                if neighbor < i {
+                    continue;
+                }
+                let (a, b) = region.edge(k);
+                let (a, b) = (frame.to_image(a), frame.to_image(b));
+                writeln!(
+                    writer,
+                    r#"    <line x1="{:.2}" y1="{:.2}" x2="{:.2}" y2="{:.2}"/>"#,
+                    a.x, a.y, b.x, b.y
+                )?;
+            }
+        }
+        writeln!(
+            writer,
+            r#"    <rect id="boundary" x="0" y="0" width="{:.2}" height="{:.2}" stroke="{}"/>"#,
+            frame.width(),
+            frame.height(),
+            teal
+        )?;
+        writeln!(writer, "  </g>")?;
+    }
+
+    if options.sites {
+        let red = RGB::from(<[f32; 3]>::from(PresetColors::RED)).to_hex();
+        writeln!(
+            writer,
+            r#"  <g id="sites" inkscape:groupmode="layer" inkscape:label="Sites" fill="{}">"#,
+            red
+        )?;
+        for (i, region) in regions.iter().enumerate() {
+            let site = frame.to_image(region.site);
+            writeln!(
+                writer,
+                r#"    <circle cx="{:.2}" cy="{:.2}" r="{}" data-region="{}"/>"#,
+                site.x, site.y, options.site_radius, i
+            )?;
+        }
+        writeln!(writer, "  </g>")?;
+    }
+    writeln!(writer, "</svg>")
+}
+
+#[cfg(test)]
+mod svg_tests {
+    use super::*;
+    use crate::map::{test_map, Wrap};
+
+    #[test]
+    fn test_every_region_is_a_polygon() {
+        let map = test_map(Wrap::None);
+        let svg = map_to_svg(&map, &SvgOptions::default());
+        assert!(svg.starts_with("<svg"));
+        assert!(svg.trim_end().ends_with("</svg>"));
+        assert!(svg.contains(r#"viewBox="0 0 640.00 640.00""#));
+        assert_eq!(svg.matches("<polygon").count(), map.get_regions().len());
+        assert!(svg.contains(r#"data-region="0" "#));
+        let last = map.get_regions().len() - 1;
+        assert!(svg.contains(&format!(r#"data-region="{}" "#, last)));
+        assert_eq!(svg.matches("<g ").count(), svg.matches("</g>").count());
+        assert!(svg.contains(r#"id="boundary""#));
+        assert!(!svg.contains("<circle"));
+    }
+
+    #[test]
+    fn test_layers_by_biome() {
+        let map = test_map(Wrap::None);
+        let options = SvgOptions {
+            biome_palette: true,
+            edges: false,
+            sites: true,
+            ..Default::default()
+        };
+        let svg = map_to_svg(&map, &options);
+        let biomes: std::collections::HashSet<Biome> = map
+            .get_regions()
+            .iter()
+            .map(|region| region.biome())
+            .collect();
+        assert_eq!(
+            svg.matches(r#"inkscape:groupmode="layer""#).count(),
+            biomes.len() + 1
+        );
+        assert!(svg.contains(&Biome::Ocean.to_color().to_hex()));
+        assert!(!svg.contains("<line"));
+        assert_eq!(svg.matches("<circle").count(), map.get_regions().len());
+    }
+}
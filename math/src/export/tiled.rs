@@ -0,0 +1,250 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use nalgebra_glm::Vec2;
+
+use crate::map::{Biome, Map};
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum TileShape {
+    Square,
+    /// Pointy top hexagons with every odd row pushed half a tile to the right, Tiled's
+    /// `staggeraxis="y"` and `staggerindex="odd"`.
+    Hexagonal,
+}
+
+#[derive(Debug, Clone)]
+pub struct TiledOptions {
+    pub columns: u32,
+    pub rows: u32,
+    /// In pixels.
+    pub tile_width: u32,
+    pub tile_height: u32,
+    pub shape: TileShape,
+    /// Path of the `.tsx` tileset, relative to the `.tmx` file.
+    pub tileset: String,
+    /// Local tile id in the tileset for each biome. Biomes left out stay empty.
+    pub tiles: BTreeMap<Biome, u32>,
+}
+
+impl Default for TiledOptions {
+    fn default() -> Self {
+        Self {
+            columns: 64,
+            rows: 64,
+            tile_width: 32,
+            tile_height: 32,
+            shape: TileShape::Square,
+            tileset: "biomes.tsx".to_string(),
+            tiles: Biome::ALL
+                .iter()
+                .enumerate()
+                .map(|(id, &biome)| (biome, id as u32))
+                .collect(),
+        }
+    }
+}
+
+impl TiledOptions {
+    /// Rejects grids without columns, which can't be split into rows, and grids with more tiles
+    /// than a `u32` can count.
+    pub fn validate(&self) -> io::Result<()> {
+        if self.columns == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "a tile grid needs at least one column",
+            ));
+        }
+        if self.columns.checked_mul(self.rows).is_none() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} x {} tiles are too many", self.columns, self.rows),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Center of a tile in `[0, 1]`, from the top left corner of the grid.
+    fn tile_center(&self, column: u32, row: u32) -> Vec2 {
+        let (column, row) = (column as f32, row as f32);
+        match self.shape {
+            TileShape::Square => Vec2::new(
+                (column + 0.5) / self.columns as f32,
+                (row + 0.5) / self.rows as f32,
+            ),
+            TileShape::Hexagonal => {
+                let stagger = if row as u32 % 2 == 1 { 0.5 } else { 0. };
+                Vec2::new(
+                    (column + 0.5 + stagger) / (self.columns as f32 + 0.5),
+                    (row + 0.5) / self.rows as f32,
+                )
+            }
+        }
+    }
+}
+
+/// Global tile ids of the `terrain` and `water` layers, row by row, 0 for an empty tile.
+pub fn tile_layers(map: &Map, options: &TiledOptions) -> io::Result<(Vec<u32>, Vec<u32>)> {
+    options.validate()?;
+    let boundary = map.get_boundary();
+    let top_left = boundary.top_left();
+    let size = Vec2::new(boundary.width(), -boundary.height());
+    let count = (options.columns * options.rows) as usize;
+    let (mut terrain, mut water) = (vec![0; count], vec![0; count]);
+    for row in 0..options.rows {
+        for column in 0..options.columns {
+            let point = top_left + options.tile_center(column, row).component_mul(&size);
+            let Some(region) = map.region_at(point) else {
+                continue;
+            };
+            let region = &map.get_regions()[region];
+            let Some(&id) = options.tiles.get(&region.biome()) else {
+                continue;
+            };
+            let layer = if region.is_water() {
+                &mut water
+            } else {
+                &mut terrain
+            };
+            // the tileset is always the first one
+            layer[(row * options.columns + column) as usize] = id + 1;
+        }
+    }
+    Ok((terrain, water))
+}
+
+pub fn write_tmx<W: Write>(map: &Map, options: &TiledOptions, mut writer: W) -> io::Result<()> {
+    let (terrain, water) = tile_layers(map, options)?;
+    writeln!(writer, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    let orientation = match options.shape {
+        TileShape::Square => r#"orientation="orthogonal""#.to_string(),
+        TileShape::Hexagonal => format!(
+            r#"orientation="hexagonal" hexsidelength="{}" staggeraxis="y" staggerindex="odd""#,
+            options.tile_height / 2
+        ),
+    };
+    writeln!(
+        writer,
+        r#"<map version="1.10" {} renderorder="right-down" width="{}" height="{}" tilewidth="{}" tileheight="{}" infinite="0" nextlayerid="3" nextobjectid="1">"#,
+        orientation, options.columns, options.rows, options.tile_width, options.tile_height
+    )?;
+    writeln!(
+        writer,
+        r#" <tileset firstgid="1" source="{}"/>"#,
+        escape(&options.tileset)
+    )?;
+    for (id, (name, tiles)) in [("terrain", terrain), ("water", water)]
+        .into_iter()
+        .enumerate()
+    {
+        writeln!(
+            writer,
+            r#" <layer id="{}" name="{}" width="{}" height="{}">"#,
+            id + 1,
+            name,
+            options.columns,
+            options.rows
+        )?;
+        writeln!(writer, r#"  <data encoding="csv">"#)?;
+        let rows: Vec<String> = tiles
+            .chunks(options.columns as usize)
+            .map(|row| {
+                row.iter()
+                    .map(|tile| tile.to_string())
+                    .collect::<Vec<_>>()
+                    .join(",")
+            })
+            .collect();
+        writeln!(writer, "{}", rows.join(",\n"))?;
+        writeln!(writer, "  </data>")?;
+        writeln!(writer, " </layer>")?;
+    }
+    writeln!(writer, "</map>")
+}
+
+pub fn save_tmx(map: &Map, options: &TiledOptions, path: impl AsRef<Path>) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    write_tmx(map, options, &mut writer)?;
+    writer.flush()
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tiled_tests {
+    use super::*;
+    use crate::map::{test_map, Wrap};
+
+    #[test]
+    fn test_layers_split_land_and_water() {
+        let map = test_map(Wrap::None);
+        let options = TiledOptions {
+            columns: 40,
+            rows: 30,
+            ..Default::default()
+        };
+        let (terrain, water) = tile_layers(&map, &options).unwrap();
+        assert_eq!(terrain.len(), 40 * 30);
+        for (&land, &sea) in terrain.iter().zip(&water) {
+            assert!((land == 0) != (sea == 0));
+        }
+        assert!(terrain.iter().any(|&tile| tile != 0));
+        assert!(water.iter().any(|&tile| tile != 0));
+
+        let ocean = options.tiles[&Biome::Ocean] + 1;
+        assert!(!terrain.contains(&ocean));
+        let mut tiles = options.tiles.clone();
+        tiles.remove(&Biome::Ocean);
+        let (_, without_ocean) = tile_layers(&map, &TiledOptions { tiles, ..options }).unwrap();
+        assert!(!without_ocean.contains(&ocean));
+        let filled = |layer: &[u32]| layer.iter().filter(|&&tile| tile != 0).count();
+        assert!(filled(&without_ocean) < filled(&water));
+    }
+
+    #[test]
+    fn test_tmx() {
+        let options = TiledOptions {
+            columns: 10,
+            rows: 6,
+            shape: TileShape::Hexagonal,
+            ..Default::default()
+        };
+        let mut bytes = vec![];
+        write_tmx(&test_map(Wrap::None), &options, &mut bytes).unwrap();
+        let tmx = String::from_utf8(bytes).unwrap();
+        assert!(tmx.contains(r#"orientation="hexagonal""#));
+        assert!(tmx.contains(r#"<tileset firstgid="1" source="biomes.tsx"/>"#));
+        assert_eq!(tmx.matches("<layer ").count(), 2);
+        for layer in tmx.split(r#"<data encoding="csv">"#).skip(1) {
+            let csv = &layer[..layer.find("</data>").unwrap()];
+            assert_eq!(csv.trim().lines().count(), 6);
+            assert_eq!(csv.split(',').count(), 60);
+        }
+    }
+
+    #[test]
+    fn test_rejects_bad_grids() {
+        let map = test_map(Wrap::None);
+        for (columns, rows) in [(0, 6), (u32::MAX, 2), (1 << 16, 1 << 16)] {
+            let options = TiledOptions {
+                columns,
+                rows,
+                ..Default::default()
+            };
+            let error = write_tmx(&map, &options, vec![]).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        }
+        let empty = TiledOptions {
+            rows: 0,
+            ..Default::default()
+        };
+        assert_eq!(tile_layers(&map, &empty).unwrap(), (vec![], vec![]));
+    }
+}
@@ -0,0 +1,435 @@
+//! Regression checks of generated maps against stored golden files.
+//!
+//! Set `UPDATE_GOLDEN=1` to write the golden files again after an intended change.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::map::Map;
+
+/// Step positions, elevation and moisture are rounded to before being compared or hashed.
+pub const QUANTUM: f32 = 1e-3;
+
+/// How many changed regions a diff lists before it stops.
+const MAX_LISTED: usize = 20;
+
+/// A region rounded to a grid of `quantum`, described without any index so it doesn't depend
+/// on the order regions are stored in.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct RegionRecord {
+    pub site: [i64; 2],
+    /// Starting from the smallest corner, in the polygon order.
+    pub corners: Vec<[i64; 2]>,
+    /// Sites of the neighbors, sorted.
+    pub neighbors: Vec<[i64; 2]>,
+    pub elevation: i64,
+    pub moisture: i64,
+    pub biome: String,
+}
+
+/// Every region of a map whose site is inside the boundary, sorted by site. The cells of the
+/// sites placed outside only close the ones along the edge, their far corners come from nearly
+/// flat triangles and move with the slightest rounding change.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MapRecord {
+    pub quantum: f32,
+    pub regions: Vec<RegionRecord>,
+}
+
+impl MapRecord {
+    pub fn new(map: &Map, quantum: f32) -> Self {
+        let round = |value: f32| (value / quantum).round() as i64;
+        let point = |p: nalgebra_glm::Vec2| [round(p.x), round(p.y)];
+        let regions = map.get_regions();
+        let (min, max) = map.clip_rect();
+        let mut records: Vec<RegionRecord> = regions
+            .iter()
+            .filter(|region| {
+                let site = region.site;
+                site.x >= min.x && site.y >= min.y && site.x <= max.x && site.y <= max.y
+            })
+            .map(|region| {
+                let mut corners: Vec<[i64; 2]> = region.polygon().into_iter().map(point).collect();
+                if let Some(first) = (0..corners.len()).min_by_key(|&k| corners[k]) {
+                    corners.rotate_left(first);
+                }
+                let mut neighbors: Vec<[i64; 2]> = region
+                    .neighbors
+                    .iter()
+                    .map(|&neighbor| point(regions[neighbor].site))
+                    .collect();
+                neighbors.sort();
+                RegionRecord {
+                    site: point(region.site),
+                    corners,
+                    neighbors,
+                    elevation: round(region.elevation),
+                    moisture: round(region.moisture),
+                    biome: format!("{:?}", region.biome()),
+                }
+            })
+            .collect();
+        records.sort();
+        Self {
+            quantum,
+            regions: records,
+        }
+    }
+
+    /// FNV-1a of the records, the same on every platform and Rust version.
+    pub fn hash(&self) -> u64 {
+        let mut hasher = StableHasher::default();
+        self.regions.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// One region per line so the files diff well in version control.
+    pub fn to_json(&self) -> String {
+        let regions: Vec<String> = self
+            .regions
+            .iter()
+            .map(|region| serde_json::to_string(region).unwrap())
+            .collect();
+        format!(
+            "{{\"quantum\":{},\"regions\":[\n{}\n]}}\n",
+            self.quantum,
+            regions.join(",\n")
+        )
+    }
+
+    pub fn from_json(text: &str) -> serde_json::Result<Self> {
+        serde_json::from_str(text)
+    }
+
+    /// Regions of `self` missing from `other`, the other way around, and the ones on the same
+    /// site that differ.
+    pub fn diff(&self, other: &MapRecord) -> MapDiff {
+        let by_site = |record: &MapRecord| -> BTreeMap<[i64; 2], RegionRecord> {
+            record
+                .regions
+                .iter()
+                .map(|region| (region.site, region.clone()))
+                .collect()
+        };
+        let (old, new) = (by_site(self), by_site(other));
+        let mut diff = MapDiff {
+            quantum: self.quantum,
+            ..Default::default()
+        };
+        for (site, region) in &old {
+            match new.get(site) {
+                None => diff.removed.push(region.clone()),
+                Some(changed) if changed != region => {
+                    diff.changed.push((region.clone(), changed.clone()))
+                }
+                Some(_) => {}
+            }
+        }
+        diff.added = new
+            .into_iter()
+            .filter(|(site, _)| !old.contains_key(site))
+            .map(|(_, region)| region)
+            .collect();
+        diff
+    }
+}
+
+impl Map {
+    /// Hash of the map content rounded to `QUANTUM`, independent of the region order.
+    pub fn content_hash(&self) -> u64 {
+        MapRecord::new(self, QUANTUM).hash()
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MapDiff {
+    pub quantum: f32,
+    pub removed: Vec<RegionRecord>,
+    pub added: Vec<RegionRecord>,
+    /// Old and new version of the region.
+    pub changed: Vec<(RegionRecord, RegionRecord)>,
+}
+
+impl MapDiff {
+    pub fn is_empty(&self) -> bool {
+        self.removed.is_empty() && self.added.is_empty() && self.changed.is_empty()
+    }
+}
+
+impl fmt::Display for MapDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let digits = (-self.quantum.log10()).ceil().max(0.) as usize;
+        let value = |value: i64| format!("{:.*}", digits, value as f32 * self.quantum);
+        let site = |site: [i64; 2]| format!("({}, {})", value(site[0]), value(site[1]));
+        writeln!(
+            f,
+            "{} regions changed, {} removed, {} added",
+            self.changed.len(),
+            self.removed.len(),
+            self.added.len()
+        )?;
+        let mut lines = vec![];
+        for region in &self.removed {
+            lines.push(format!("- region at {}", site(region.site)));
+        }
+        for region in &self.added {
+            lines.push(format!("+ region at {}", site(region.site)));
+        }
+        for (old, new) in &self.changed {
+            let mut changes = vec![];
+            if old.elevation != new.elevation {
+                changes.push(format!(
+                    "elevation {} -> {}",
+                    value(old.elevation),
+                    value(new.elevation)
+                ));
+            }
+            if old.moisture != new.moisture {
+                changes.push(format!(
+                    "moisture {} -> {}",
+                    value(old.moisture),
+                    value(new.moisture)
+                ));
+            }
+            if old.biome != new.biome {
+                changes.push(format!("biome {} -> {}", old.biome, new.biome));
+            }
+            if old.corners != new.corners {
+                changes.push(format!(
+                    "corners moved ({} -> {} corners)",
+                    old.corners.len(),
+                    new.corners.len()
+                ));
+            }
+            if old.neighbors != new.neighbors {
+                changes.push("neighbors changed".to_string());
+            }
+            lines.push(format!(
+                "~ region at {}: {}",
+                site(old.site),
+                changes.join(", ")
+            ));
+        }
+        for line in lines.iter().take(MAX_LISTED) {
+            writeln!(f, "  {}", line)?;
+        }
+        if lines.len() > MAX_LISTED {
+            writeln!(f, "  ... and {} more", lines.len() - MAX_LISTED)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub enum GoldenError {
+    Io(PathBuf, io::Error),
+    Json(PathBuf, serde_json::Error),
+    Mismatch(PathBuf, MapDiff),
+}
+
+impl fmt::Display for GoldenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GoldenError::Io(path, error) => write!(f, "{}: {}", path.display(), error),
+            GoldenError::Json(path, error) => {
+                write!(f, "{}: invalid golden file: {}", path.display(), error)
+            }
+            GoldenError::Mismatch(path, diff) => write!(
+                f,
+                "{} doesn't match, rerun with UPDATE_GOLDEN=1 if the change is intended\n{}",
+                path.display(),
+                diff
+            ),
+        }
+    }
+}
+
+impl std::error::Error for GoldenError {}
+
+/// Compares `map` with the golden file `dir/name.json`, or writes the file when `UPDATE_GOLDEN`
+/// is set.
+pub fn check_golden(map: &Map, dir: impl AsRef<Path>, name: &str) -> Result<(), GoldenError> {
+    let path = dir.as_ref().join(format!("{}.json", name));
+    let record = MapRecord::new(map, QUANTUM);
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        return fs::create_dir_all(dir.as_ref())
+            .and_then(|()| fs::write(&path, record.to_json()))
+            .map_err(|error| GoldenError::Io(path, error));
+    }
+    let text = match fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(error) => return Err(GoldenError::Io(path, error)),
+    };
+    let golden = match MapRecord::from_json(&text) {
+        Ok(golden) => golden,
+        Err(error) => return Err(GoldenError::Json(path, error)),
+    };
+    let diff = golden.diff(&record);
+    if diff.is_empty() {
+        Ok(())
+    } else {
+        Err(GoldenError::Mismatch(path, diff))
+    }
+}
+
+/// `check_golden` for tests, panics with the diff.
+#[track_caller]
+pub fn assert_golden(map: &Map, dir: impl AsRef<Path>, name: &str) {
+    if let Err(error) = check_golden(map, dir, name) {
+        panic!("{}", error);
+    }
+}
+
+/// 64 bit FNV-1a, with integers written little endian and lengths as `u64`.
+struct StableHasher(u64);
+
+impl Default for StableHasher {
+    fn default() -> Self {
+        Self(0xcbf29ce484222325)
+    }
+}
+
+impl Hasher for StableHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 = (self.0 ^ byte as u64).wrapping_mul(0x100000001b3);
+        }
+    }
+
+    fn write_usize(&mut self, value: usize) {
+        self.write(&(value as u64).to_le_bytes());
+    }
+
+    fn write_i64(&mut self, value: i64) {
+        self.write(&value.to_le_bytes());
+    }
+}
+
+#[cfg(test)]
+mod golden_tests {
+    use super::*;
+    use crate::map::{generate_map, DistanceFn, MapSettings, ReshapingFn, Wrap};
+    use crate::Boundary;
+    use nalgebra_glm::Vec2;
+
+    fn golden_dir() -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("golden")
+    }
+
+    fn cases() -> Vec<(String, MapSettings)> {
+        let boundary = Boundary::from_top_left(Vec2::new(-12., 12.), 24., 24.);
+        let configs = [
+            (
+                "euclidean",
+                DistanceFn::Euclidean,
+                ReshapingFn::Linear,
+                Wrap::None,
+            ),
+            (
+                "diagonal",
+                DistanceFn::Diagonal,
+                ReshapingFn::Flat,
+                Wrap::None,
+            ),
+            (
+                "horizontal",
+                DistanceFn::Manhattan,
+                ReshapingFn::Smooth,
+                Wrap::Horizontal,
+            ),
+            (
+                "torus",
+                DistanceFn::Euclidean,
+                ReshapingFn::Flat,
+                Wrap::Both,
+            ),
+        ];
+        let mut cases = vec![];
+        for seed in [1, 12345] {
+            for (name, distance, reshape, wrap) in configs {
+                let settings = MapSettings::new(boundary.clone(), seed, distance, reshape)
+                    .with_site_count(150)
+                    .with_wrap(wrap);
+                cases.push((format!("{}_{}", name, seed), settings));
+            }
+        }
+        cases
+    }
+
+    #[test]
+    fn test_golden_maps() {
+        for (name, settings) in cases() {
+            assert_golden(&generate_map(settings).unwrap(), golden_dir(), &name);
+        }
+    }
+
+    #[test]
+    fn test_hash_ignores_region_order() {
+        let (_, settings) = cases().remove(0);
+        let map = generate_map(settings.clone()).unwrap();
+        assert_eq!(
+            map.content_hash(),
+            generate_map(settings.clone()).unwrap().content_hash()
+        );
+
+        let mut regions = map.get_regions().clone();
+        let last = regions.len() - 1;
+        regions.swap(0, last);
+        for region in &mut regions {
+            for neighbor in &mut region.neighbors {
+                if *neighbor == 0 {
+                    *neighbor = last;
+                } else if *neighbor == last {
+                    *neighbor = 0;
+                }
+            }
+        }
+        let shuffled = Map::from_regions(settings.clone(), regions, vec![]).unwrap();
+        assert_eq!(map.content_hash(), shuffled.content_hash());
+
+        let other = generate_map(MapSettings {
+            seed: 2,
+            ..settings
+        })
+        .unwrap();
+        assert_ne!(map.content_hash(), other.content_hash());
+    }
+
+    #[test]
+    fn test_diff() {
+        let (_, settings) = cases().remove(0);
+        let map = generate_map(settings.clone()).unwrap();
+        let mut regions = map.get_regions().clone();
+        regions[map.region_at(Vec2::zeros()).unwrap()].elevation += 0.25;
+        let edited = Map::from_regions(settings, regions, vec![]).unwrap();
+
+        let (old, new) = (
+            MapRecord::new(&map, QUANTUM),
+            MapRecord::new(&edited, QUANTUM),
+        );
+        // the cells of the sites outside the boundary are left out
+        let reach = (12. + 3.) / QUANTUM;
+        assert!(old.regions.len() < map.get_regions().len());
+        assert!(old
+            .regions
+            .iter()
+            .flat_map(|region| &region.corners)
+            .all(|corner| corner.iter().all(|&c| (c as f32).abs() < reach)));
+        assert!(old.diff(&old).is_empty());
+        let diff = old.diff(&new);
+        assert_eq!(diff.changed.len(), 1);
+        assert!(diff.removed.is_empty() && diff.added.is_empty());
+        assert!(diff.to_string().contains("elevation"));
+        assert_eq!(MapRecord::from_json(&old.to_json()).unwrap(), old);
+    }
+}
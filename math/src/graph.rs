@@ -0,0 +1,379 @@
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, VecDeque};
+
+use crate::map::{Map, MapRegion, RegionIndex};
+
+#[derive(Debug, Clone)]
+pub struct Path {
+    /// Both ends included.
+    pub regions: Vec<RegionIndex>,
+    pub cost: f32,
+}
+
+/// Cost for every region to reach the nearest of a set of sources.
+#[derive(Debug, Clone)]
+pub struct CostField {
+    /// `f32::INFINITY` where no source could be reached within the radius.
+    pub costs: Vec<f32>,
+    /// Index into the sources of the one each region is closest to.
+    pub nearest_source: Vec<Option<usize>>,
+}
+
+impl CostField {
+    pub fn cost(&self, region: RegionIndex) -> Option<f32> {
+        let cost = self.costs[region];
+        cost.is_finite().then_some(cost)
+    }
+
+    pub fn max_cost(&self) -> f32 {
+        self.costs
+            .iter()
+            .copied()
+            .filter(|cost| cost.is_finite())
+            .fold(0.0, f32::max)
+    }
+
+    /// Costs scaled to `[0, 1]`, `None` for unreached regions.
+    pub fn normalized(&self) -> Vec<Option<f32>> {
+        let max = self.max_cost();
+        (0..self.costs.len())
+            .map(|region| {
+                self.cost(region)
+                    .map(|cost| if max > 0. { cost / max } else { 0. })
+            })
+            .collect()
+    }
+}
+
+impl Map {
+    /// Multi-source Dijkstra, regions farther than `radius` are left unreached.
+    /// `cost_fn` works like in `find_path`. Sources that aren't regions of the map are skipped.
+    pub fn cost_field<F>(&self, sources: &[RegionIndex], radius: f32, cost_fn: F) -> CostField
+    where
+        F: Fn(&MapRegion, &MapRegion) -> Option<f32>,
+    {
+        let regions = self.get_regions();
+        let mut costs = vec![f32::INFINITY; regions.len()];
+        let mut nearest_source = vec![None; regions.len()];
+        let mut done = vec![false; regions.len()];
+        let mut open = BinaryHeap::new();
+        for (source, &region) in sources.iter().enumerate() {
+            if region < regions.len() && costs[region] > 0. {
+                costs[region] = 0.;
+                nearest_source[region] = Some(source);
+                open.push(Candidate::new(0., region, source));
+            }
+        }
+
+        while let Some(Candidate {
+            cost,
+            region,
+            origin,
+        }) = open.pop()
+        {
+            if done[region] {
+                continue;
+            }
+            done[region] = true;
+            let current = &regions[region];
+            for &neighbor in &current.neighbors {
+                let step = match cost_fn(current, &regions[neighbor]) {
+                    Some(step) => step,
+                    None => continue,
+                };
+                let cost = cost + step;
+                if cost <= radius && cost < costs[neighbor] {
+                    costs[neighbor] = cost;
+                    nearest_source[neighbor] = Some(origin);
+                    open.push(Candidate::new(cost, neighbor, origin));
+                }
+            }
+        }
+        CostField {
+            costs,
+            nearest_source,
+        }
+    }
+
+    /// Breadth first version of `cost_field` where every step costs 1.
+    pub fn hop_field(&self, sources: &[RegionIndex], max_hops: usize) -> CostField {
+        let regions = self.get_regions();
+        let mut costs = vec![f32::INFINITY; regions.len()];
+        let mut nearest_source = vec![None; regions.len()];
+        let mut open = VecDeque::new();
+        for (source, &region) in sources.iter().enumerate() {
+            if region < regions.len() && nearest_source[region].is_none() {
+                costs[region] = 0.;
+                nearest_source[region] = Some(source);
+                open.push_back((region, 0));
+            }
+        }
+        while let Some((region, hops)) = open.pop_front() {
+            if hops == max_hops {
+                continue;
+            }
+            for &neighbor in &regions[region].neighbors {
+                if nearest_source[neighbor].is_none() {
+                    costs[neighbor] = (hops + 1) as f32;
+                    nearest_source[neighbor] = nearest_source[region];
+                    open.push_back((neighbor, hops + 1));
+                }
+            }
+        }
+        CostField {
+            costs,
+            nearest_source,
+        }
+    }
+
+    /// Land regions touching water.
+    pub fn coast(&self) -> Vec<RegionIndex> {
+        let regions = self.get_regions();
+        (0..regions.len())
+            .filter(|&i| {
+                !regions[i].is_water()
+                    && regions[i].neighbors.iter().any(|&n| regions[n].is_water())
+            })
+            .collect()
+    }
+
+    /// A* over the region adjacency. `cost_fn` prices a step between two neighbors and returns
+    /// `None` when the step is impassable. The heuristic is the straight distance between sites,
+    /// across wrapping edges, so the path is only guaranteed optimal when no step costs less
+    /// than that distance. `None` as well when `from` or `to` isn't a region of the map.
+    pub fn find_path<F>(&self, from: RegionIndex, to: RegionIndex, cost_fn: F) -> Option<Path>
+    where
+        F: Fn(&MapRegion, &MapRegion) -> Option<f32>,
+    {
+        let regions = self.get_regions();
+        if from >= regions.len() || to >= regions.len() {
+            return None;
+        }
+        let goal = regions[to].site;
+        let mut best = vec![f32::INFINITY; regions.len()];
+        let mut came_from = vec![None; regions.len()];
+        let mut open = BinaryHeap::new();
+        best[from] = 0.0;
+        open.push(Candidate::new(
+            self.wrapped_delta(regions[from].site, goal).norm(),
+            from,
+            from,
+        ));
+
+        while let Some(Candidate { region, .. }) = open.pop() {
+            if region == to {
+                return Some(Path {
+                    regions: rebuild_path(&came_from, to),
+                    cost: best[to],
+                });
+            }
+            let current = &regions[region];
+            for &neighbor in &current.neighbors {
+                let step = match cost_fn(current, &regions[neighbor]) {
+                    Some(step) => step,
+                    None => continue,
+                };
+                let cost = best[region] + step;
+                if cost < best[neighbor] {
+                    best[neighbor] = cost;
+                    came_from[neighbor] = Some(region);
+                    let estimate = cost + self.wrapped_delta(regions[neighbor].site, goal).norm();
+                    open.push(Candidate::new(estimate, neighbor, region));
+                }
+            }
+        }
+        None
+    }
+}
+
+fn rebuild_path(came_from: &[Option<RegionIndex>], to: RegionIndex) -> Vec<RegionIndex> {
+    let mut path = vec![to];
+    let mut current = to;
+    while let Some(previous) = came_from[current] {
+        path.push(previous);
+        current = previous;
+    }
+    path.reverse();
+    path
+}
+
+/// Min-heap entry, `origin` is whatever the search needs to remember about how it got there.
+#[derive(PartialEq)]
+pub(crate) struct Candidate {
+    pub(crate) cost: f32,
+    pub(crate) region: RegionIndex,
+    pub(crate) origin: usize,
+}
+
+impl Candidate {
+    pub(crate) fn new(cost: f32, region: RegionIndex, origin: usize) -> Self {
+        Self {
+            cost,
+            region,
+            origin,
+        }
+    }
+}
+
+impl Eq for Candidate {}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // reversed so the BinaryHeap pops the cheapest region first
+        other
+            .cost
+            .total_cmp(&self.cost)
+            .then_with(|| other.region.cmp(&self.region))
+            .then_with(|| other.origin.cmp(&self.origin))
+    }
+}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+#[cfg(test)]
+mod graph_tests {
+    use super::*;
+    use crate::map::{test_map, Wrap};
+    use nalgebra_glm::Vec2;
+
+    fn distance(map: &Map) -> impl Fn(&MapRegion, &MapRegion) -> Option<f32> + '_ {
+        |a, b| Some(map.wrapped_delta(a.site, b.site).norm())
+    }
+
+    #[test]
+    fn test_path_follows_neighbors() {
+        let map = test_map(Wrap::None);
+        let from = map.region_at(Vec2::new(-20., -20.)).unwrap();
+        let to = map.region_at(Vec2::new(20., 15.)).unwrap();
+        let path = map.find_path(from, to, distance(&map)).unwrap();
+        assert_eq!(path.regions.first(), Some(&from));
+        assert_eq!(path.regions.last(), Some(&to));
+        for step in path.regions.windows(2) {
+            assert!(map.get_regions()[step[0]].neighbors.contains(&step[1]));
+        }
+        let straight = map.site_delta(from, to).norm();
+        assert!(path.cost >= straight);
+        assert!(path.cost < straight * 1.5);
+    }
+
+    #[test]
+    fn test_path_across_the_seam() {
+        let map = test_map(Wrap::Horizontal);
+        let from = map.region_at(Vec2::new(-30., 0.)).unwrap();
+        let to = map.region_at(Vec2::new(30., 0.)).unwrap();
+        let straight = map.site_delta(from, to).norm();
+        assert!(straight < 8.);
+        let path = map.find_path(from, to, distance(&map)).unwrap();
+        assert!(path.cost >= straight);
+        assert!(path.cost < straight * 1.5);
+        assert!(path
+            .regions
+            .iter()
+            .all(|&region| map.get_regions()[region].site.x.abs() > 20.));
+    }
+
+    #[test]
+    fn test_path_to_itself() {
+        let map = test_map(Wrap::None);
+        let path = map.find_path(10, 10, distance(&map)).unwrap();
+        assert_eq!(path.regions, vec![10]);
+        assert_eq!(path.cost, 0.0);
+    }
+
+    #[test]
+    fn test_out_of_range_regions() {
+        let map = test_map(Wrap::None);
+        let count = map.get_regions().len();
+        assert!(map.find_path(count, 10, distance(&map)).is_none());
+        assert!(map.find_path(10, count + 3, distance(&map)).is_none());
+        let field = map.cost_field(&[count, 10], f32::INFINITY, distance(&map));
+        assert_eq!(field.cost(10), Some(0.));
+        assert_eq!(field.nearest_source[10], Some(1));
+        assert!(field.costs.iter().all(|cost| cost.is_finite()));
+        let hops = map.hop_field(&[usize::MAX, 10], 1);
+        assert_eq!(hops.nearest_source[10], Some(1));
+    }
+
+    #[test]
+    fn test_cost_field_sources() {
+        let map = test_map(Wrap::None);
+        let sources = [
+            map.region_at(Vec2::new(-10., 0.)).unwrap(),
+            map.region_at(Vec2::new(10., 0.)).unwrap(),
+        ];
+        let field = map.cost_field(&sources, f32::INFINITY, distance(&map));
+        assert_eq!(field.cost(sources[0]), Some(0.));
+        assert_eq!(field.nearest_source[sources[1]], Some(1));
+        let left = map.region_at(Vec2::new(-12., 1.)).unwrap();
+        assert_eq!(field.nearest_source[left], Some(0));
+
+        let regions = map.get_regions();
+        for (region, &cost) in field.costs.iter().enumerate() {
+            for &neighbor in &regions[region].neighbors {
+                let step = map.site_delta(region, neighbor).norm();
+                assert!(cost <= field.costs[neighbor] + step + 1e-3);
+            }
+        }
+    }
+
+    #[test]
+    fn test_cost_field_radius() {
+        let map = test_map(Wrap::None);
+        let source = map.region_at(Vec2::new(0., 0.)).unwrap();
+        let field = map.cost_field(&[source], 5., distance(&map));
+        let far = map.region_at(Vec2::new(10., 0.)).unwrap();
+        assert_eq!(field.cost(far), None);
+        assert!(field.max_cost() <= 5.);
+        assert!(field
+            .normalized()
+            .iter()
+            .flatten()
+            .all(|&v| (0. ..=1.).contains(&v)));
+    }
+
+    #[test]
+    fn test_hop_field() {
+        let map = test_map(Wrap::None);
+        let source = map.region_at(Vec2::new(0., 0.)).unwrap();
+        let field = map.hop_field(&[source], 2);
+        assert_eq!(field.cost(source), Some(0.));
+        for &neighbor in &map.get_regions()[source].neighbors {
+            assert_eq!(field.cost(neighbor), Some(1.));
+        }
+        assert!(field.max_cost() <= 2.);
+    }
+
+    #[test]
+    fn test_coast_touches_water() {
+        let map = test_map(Wrap::None);
+        let coast = map.coast();
+        assert!(!coast.is_empty());
+        let regions = map.get_regions();
+        for &region in &coast {
+            assert!(!regions[region].is_water());
+        }
+    }
+
+    #[test]
+    fn test_impassable_regions() {
+        let map = test_map(Wrap::None);
+        let from = map.region_at(Vec2::new(0., 0.)).unwrap();
+        let to = map.region_at(Vec2::new(0., 10.)).unwrap();
+        let walls = |_: &MapRegion, b: &MapRegion| (b.site.y < 5.).then_some(1.0);
+        assert!(map.find_path(from, to, walls).is_none());
+
+        let avoid_band = |a: &MapRegion, b: &MapRegion| {
+            (b.site.x < -2. || b.site.x > 2. || b.site.y < 3. || b.site.y > 6.)
+                .then(|| (a.site - b.site).norm())
+        };
+        let path = map.find_path(from, to, avoid_band).unwrap();
+        for &region in &path.regions {
+            let site = map.get_regions()[region].site;
+            assert!(site.x < -2. || site.x > 2. || site.y < 3. || site.y > 6.);
+        }
+    }
+}
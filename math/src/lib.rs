@@ -2,10 +2,24 @@ pub use nalgebra_glm as glm;
 use nalgebra_glm::Vec2;
 pub use rand;
 pub use spade;
+pub mod batch;
 pub mod color;
 pub mod delaunay;
+pub mod error;
+pub mod export;
+pub mod golden;
+pub mod graph;
 pub mod map;
+pub mod names;
+mod parallel;
+pub mod planet;
+pub mod political;
+pub mod progress;
+pub mod save;
+pub mod stats;
+pub mod validate;
 pub mod voronoi;
+pub mod world;
 
 pub type RawMat4 = [[f32; 4]; 4];
 #[inline]
@@ -268,7 +282,7 @@ fn scalar_cross_product(u: Vec2, v: Vec2) -> f32 {
     u.x * v.y - u.y * v.x
 }
 
-#[derive(Clone)]
+#[derive(Clone, PartialEq, Debug)]
 pub struct Boundary {
     origin: Vec2,
     width: f32,
@@ -283,6 +297,12 @@ impl Boundary {
             height,
         }
     }
+    pub fn width(&self) -> f32 {
+        self.width
+    }
+    pub fn height(&self) -> f32 {
+        self.height
+    }
     pub fn top_left(&self) -> Vec2 {
         self.origin
     }
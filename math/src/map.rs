@@ -1,8 +1,12 @@
-use std::collections::{BTreeSet, HashMap};
-use std::f32::consts::FRAC_PI_2;
+use std::collections::HashMap;
+use std::f32::consts::{FRAC_PI_2, PI};
+use std::sync::Arc;
 
+use crate::error::MapError;
+use crate::names::{Feature, NameGenerator};
+use crate::parallel::map_indices;
+use crate::progress::{CancellationToken, ProgressObserver, Stage, Tracker};
 use crate::spade::{InsertionError, Triangulation};
-use crate::voronoi::VoronoiCorner;
 use crate::{
     color::RGB,
     delaunay::{NormalTriangulation, VertexType},
@@ -11,13 +15,106 @@ use crate::{
 };
 use bracket_noise::prelude::*;
 use nalgebra_glm::Vec2;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use spade::handles::FixedVertexHandle;
 use spade::handles::VoronoiVertex::{Inner, Outer};
 
 type CsTriangulation = NormalTriangulation;
+pub type RegionIndex = usize;
+
+pub const SEA_LEVEL: f32 = 0.5;
+/// How many cells past a wrapping edge the sites of the opposite side are replicated.
+const WRAP_MARGIN: f32 = 3.0;
+/// Fewest cells along a wrapping axis, see `MapError::TooNarrow`.
+const MIN_WRAPPED_CELLS: i32 = 4;
+
+/// Which opposite edges of the boundary are joined together.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub enum Wrap {
+    None,
+    Horizontal,
+    Both,
+}
+
+impl Wrap {
+    pub fn horizontal(&self) -> bool {
+        *self != Wrap::None
+    }
+
+    pub fn vertical(&self) -> bool {
+        *self == Wrap::Both
+    }
+}
+
+/// Everything a map is generated from.
+#[derive(Clone, PartialEq, Debug)]
+pub struct MapSettings {
+    pub boundary: Boundary,
+    /// Drives the placement of the sites, kept apart from `seed` so the terrain can be
+    /// regenerated without rebuilding the diagram.
+    pub site_seed: u64,
+    pub seed: u64,
+    pub distance_fn: DistanceFn,
+    pub reshape_fn: ReshapingFn,
+    pub wrap: Wrap,
+    /// Roughly how many regions to spread over the boundary. `None` keeps one per unit of area.
+    pub site_count: Option<usize>,
+}
+
+impl MapSettings {
+    pub fn new(
+        boundary: Boundary,
+        seed: u64,
+        distance_fn: DistanceFn,
+        reshape_fn: ReshapingFn,
+    ) -> Self {
+        Self {
+            boundary,
+            site_seed: seed,
+            seed,
+            distance_fn,
+            reshape_fn,
+            wrap: Wrap::None,
+            site_count: None,
+        }
+    }
+
+    pub fn with_wrap(mut self, wrap: Wrap) -> Self {
+        self.wrap = wrap;
+        self
+    }
+
+    pub fn with_site_count(mut self, site_count: usize) -> Self {
+        self.site_count = Some(site_count);
+        self
+    }
+
+    fn same_sites(&self, other: &Self) -> bool {
+        self.boundary == other.boundary
+            && self.site_seed == other.site_seed
+            && self.wrap == other.wrap
+            && self.site_count == other.site_count
+    }
+
+    fn same_elevation(&self, other: &Self) -> bool {
+        self.same_sites(other)
+            && self.seed == other.seed
+            && self.distance_fn == other.distance_fn
+            && self.reshape_fn == other.reshape_fn
+    }
+
+    fn same_moisture(&self, other: &Self) -> bool {
+        self.same_sites(other) && self.seed == other.seed
+    }
+}
 
 pub struct Map {
-    triangulation: CsTriangulation,
-    boundary: Boundary,
+    triangulation: Arc<CsTriangulation>,
+    /// Region each ghost vertex of the triangulation copies, ghosts come after the regions.
+    ghosts: Arc<Vec<RegionIndex>>,
+    settings: MapSettings,
     regions: Vec<MapRegion>,
 }
 impl Map {
@@ -26,68 +123,621 @@ impl Map {
     }
 
     pub fn get_boundary(&self) -> &Boundary {
-        &self.boundary
+        &self.settings.boundary
+    }
+
+    pub fn get_seed(&self) -> u64 {
+        self.settings.seed
+    }
+
+    pub fn get_settings(&self) -> &MapSettings {
+        &self.settings
+    }
+
+    pub fn region_name(&self, names: &NameGenerator, region: RegionIndex) -> String {
+        self.feature_name(names, Feature::Region(region))
+    }
+
+    pub fn feature_name(&self, names: &NameGenerator, feature: Feature) -> String {
+        names.name_for(self.settings.seed, feature)
+    }
+
+    /// Region containing `point`, `None` outside of the boundary. On wrapping maps points
+    /// past a wrapping edge come back in from the opposite one.
+    pub fn region_at(&self, point: Vec2) -> Option<RegionIndex> {
+        let point = self.wrap_point(point);
+        if !self.get_boundary().point_inside(point) {
+            return None;
+        }
+        self.triangulation
+            .nearest_neighbor(spade::Point2::new(point.x, point.y))
+            .map(|vertex| self.resolve_vertex(vertex.fix().index()))
+    }
+
+    /// Moves `point` back inside the boundary along the wrapping axes.
+    pub fn wrap_point(&self, point: Vec2) -> Vec2 {
+        wrap_point(&self.settings.boundary, self.settings.wrap, point)
+    }
+
+    /// Translations at which the map has to be repeated to fill the neighboring tiles, the
+    /// first one is always the map itself.
+    pub fn tile_offsets(&self) -> Vec<Vec2> {
+        let boundary = self.get_boundary();
+        let (width, height) = (boundary.width(), boundary.height());
+        let mut offsets = vec![Vec2::zeros()];
+        let xs: &[f32] = if self.settings.wrap.horizontal() {
+            &[-1., 0., 1.]
+        } else {
+            &[0.]
+        };
+        let ys: &[f32] = if self.settings.wrap.vertical() {
+            &[-1., 0., 1.]
+        } else {
+            &[0.]
+        };
+        for &y in ys {
+            for &x in xs {
+                if x != 0. || y != 0. {
+                    offsets.push(Vec2::new(x * width, y * height));
+                }
+            }
+        }
+        offsets
+    }
+
+    /// Shortest vector from `from` to `to`, crossing a wrapping edge when that is closer.
+    pub fn wrapped_delta(&self, from: Vec2, to: Vec2) -> Vec2 {
+        self.tile_offsets()
+            .into_iter()
+            .map(|offset| to + offset - from)
+            .min_by(|a, b| a.norm_squared().total_cmp(&b.norm_squared()))
+            .unwrap()
     }
 
-    pub fn regenerate(&self, seed: u64, distance_fn: DistanceFn, reshape_fn: ReshapingFn) -> Self {
-        new_map(self.boundary.clone(), seed, distance_fn, reshape_fn)
+    /// `wrapped_delta` between the sites of two regions.
+    pub fn site_delta(&self, a: RegionIndex, b: RegionIndex) -> Vec2 {
+        let regions = self.get_regions();
+        self.wrapped_delta(regions[a].site, regions[b].site)
+    }
+
+    /// Position of every ghost vertex of the triangulation along with the region it copies.
+    pub(crate) fn ghost_sites(&self) -> Vec<(Vec2, RegionIndex)> {
+        self.ghosts
+            .iter()
+            .enumerate()
+            .map(|(i, &region)| {
+                let vertex = FixedVertexHandle::from_index(self.regions.len() + i);
+                (self.triangulation.vertex(vertex).data().position, region)
+            })
+            .collect()
+    }
+
+    /// Map made of already generated regions, the triangulation is rebuilt from their sites
+    /// followed by the ghost sites.
+    pub(crate) fn from_regions(
+        settings: MapSettings,
+        regions: Vec<MapRegion>,
+        ghost_sites: Vec<(Vec2, RegionIndex)>,
+    ) -> Result<Self, InsertionError> {
+        let points = regions
+            .iter()
+            .map(|region| region.site)
+            .chain(ghost_sites.iter().map(|&(site, _)| site))
+            .collect();
+        Ok(Map {
+            triangulation: Arc::new(init_triangulation_points(points)?),
+            ghosts: Arc::new(ghost_sites.into_iter().map(|(_, region)| region).collect()),
+            settings,
+            regions,
+        })
+    }
+
+    /// Vertices of the triangulation, fewer than the regions and ghosts when sites coincide.
+    pub(crate) fn vertex_count(&self) -> usize {
+        self.triangulation.num_vertices()
+    }
+
+    fn resolve_vertex(&self, vertex: usize) -> RegionIndex {
+        if vertex < self.regions.len() {
+            vertex
+        } else {
+            self.ghosts[vertex - self.regions.len()]
+        }
+    }
+
+    pub fn regions_at(&self, points: &[Vec2]) -> Vec<Option<RegionIndex>> {
+        points.iter().map(|&point| self.region_at(point)).collect()
+    }
+
+    /// Region under the center of every cell of a `columns` x `rows` grid laid over the boundary,
+    /// row by row from the top left corner.
+    pub fn region_grid(&self, columns: usize, rows: usize) -> Vec<Option<RegionIndex>> {
+        let boundary = self.get_boundary();
+        let top_left = boundary.top_left();
+        let cell_width = boundary.width() / columns as f32;
+        let cell_height = boundary.height() / rows as f32;
+        let mut grid = Vec::with_capacity(columns * rows);
+        for row in 0..rows {
+            let y = top_left.y - (row as f32 + 0.5) * cell_height;
+            for column in 0..columns {
+                let x = top_left.x + (column as f32 + 0.5) * cell_width;
+                grid.push(self.region_at(Vec2::new(x, y)));
+            }
+        }
+        grid
+    }
+
+    /// Elevation at `point` interpolated between the sites around it with natural neighbor
+    /// interpolation, so it matches the region elevation on the sites and varies smoothly
+    /// between them. `None` outside of the boundary.
+    pub fn elevation_at(&self, point: Vec2) -> Option<f32> {
+        let point = self.wrap_point(point);
+        if !self.get_boundary().point_inside(point) {
+            return None;
+        }
+        let position = spade::Point2::new(point.x, point.y);
+        self.triangulation
+            .natural_neighbor()
+            .interpolate(
+                |vertex| self.regions[self.resolve_vertex(vertex.fix().index())].elevation,
+                position,
+            )
+            .or_else(|| {
+                self.region_at(point)
+                    .map(|region| self.regions[region].elevation)
+            })
+    }
+
+    /// Groups of land regions connected through their neighbors, largest first.
+    pub fn landmasses(&self) -> Vec<Vec<RegionIndex>> {
+        self.components(|region| !region.is_water())
+    }
+
+    /// Connected groups of the regions `member` accepts, largest first.
+    pub(crate) fn components<F: Fn(&MapRegion) -> bool>(&self, member: F) -> Vec<Vec<RegionIndex>> {
+        let mut seen = vec![false; self.regions.len()];
+        let mut components = vec![];
+        for start in 0..self.regions.len() {
+            if seen[start] || !member(&self.regions[start]) {
+                continue;
+            }
+            seen[start] = true;
+            let mut component = vec![start];
+            let mut next = 0;
+            while next < component.len() {
+                for &neighbor in &self.regions[component[next]].neighbors {
+                    if neighbor < seen.len() && !seen[neighbor] && member(&self.regions[neighbor]) {
+                        seen[neighbor] = true;
+                        component.push(neighbor);
+                    }
+                }
+                next += 1;
+            }
+            components.push(component);
+        }
+        components.sort_by_key(|component| std::cmp::Reverse(component.len()));
+        components
+    }
+
+    /// `elevation_at` the center of every cell of a `columns` x `rows` grid, in the same order as
+    /// `region_grid`.
+    pub fn elevation_grid(&self, columns: usize, rows: usize) -> Vec<f32> {
+        let boundary = self.get_boundary();
+        let top_left = boundary.top_left();
+        let cell_width = boundary.width() / columns as f32;
+        let cell_height = boundary.height() / rows as f32;
+        let rows: Vec<Vec<f32>> = map_indices(rows, |row| {
+            let y = top_left.y - (row as f32 + 0.5) * cell_height;
+            (0..columns)
+                .map(|column| {
+                    let x = top_left.x + (column as f32 + 0.5) * cell_width;
+                    self.elevation_at(Vec2::new(x, y)).unwrap_or(0.)
+                })
+                .collect()
+        });
+        rows.concat()
+    }
+
+    pub fn regenerate(
+        &self,
+        seed: u64,
+        distance_fn: DistanceFn,
+        reshape_fn: ReshapingFn,
+    ) -> Result<Self, MapError> {
+        self.regenerate_with(MapSettings {
+            seed,
+            distance_fn,
+            reshape_fn,
+            ..self.settings.clone()
+        })
+    }
+
+    /// Only reruns the stages whose inputs differ from the current settings, the diagram is
+    /// shared with `self` as long as the sites stay the same.
+    pub fn regenerate_with(&self, settings: MapSettings) -> Result<Self, MapError> {
+        self.regenerate_tracked(settings, &mut Tracker::silent())
+    }
+
+    /// `regenerate_with` reporting to `observer` and stopped by `token`. Stages that can be
+    /// skipped are reported as done right away.
+    pub fn regenerate_with_progress(
+        &self,
+        settings: MapSettings,
+        observer: &dyn ProgressObserver,
+        token: &CancellationToken,
+    ) -> Result<Self, MapError> {
+        let plan: &[Stage] = if self.settings.same_sites(&settings) {
+            &[Stage::Elevation, Stage::Moisture, Stage::Biomes]
+        } else {
+            &Stage::ALL
+        };
+        self.regenerate_tracked(settings, &mut Tracker::new(plan, observer, token))
+    }
+
+    fn regenerate_tracked(
+        &self,
+        settings: MapSettings,
+        tracker: &mut Tracker,
+    ) -> Result<Self, MapError> {
+        if !self.settings.same_sites(&settings) {
+            return generate_tracked(settings, tracker);
+        }
+        let sites: Vec<Vec2> = self.regions.iter().map(|region| region.site).collect();
+        tracker.start(Stage::Elevation)?;
+        let elevation_map: Vec<f32> = if self.settings.same_elevation(&settings) {
+            self.regions.iter().map(|region| region.elevation).collect()
+        } else {
+            assign_elevation_map(&sites, &settings, tracker)?
+        };
+        tracker.finish()?;
+        tracker.start(Stage::Moisture)?;
+        let moisture_map: Vec<f32> = if self.settings.same_moisture(&settings) {
+            self.regions.iter().map(|region| region.moisture).collect()
+        } else {
+            assign_moisture_map(&sites, &settings, tracker)?
+        };
+        tracker.finish()?;
+        tracker.start(Stage::Biomes)?;
+        let regions = tracker.map_indices(self.regions.len(), |i| {
+            let region = &self.regions[i];
+            MapRegion::new(
+                region.site,
+                region.vertices.clone(),
+                region.neighbors.clone(),
+                elevation_map[i],
+                moisture_map[i],
+            )
+        })?;
+        tracker.finish()?;
+        Ok(Map {
+            triangulation: Arc::clone(&self.triangulation),
+            ghosts: Arc::clone(&self.ghosts),
+            settings,
+            regions,
+        })
+    }
+
+    pub fn shares_sites_with(&self, other: &Map) -> bool {
+        Arc::ptr_eq(&self.triangulation, &other.triangulation)
     }
 }
 
+#[derive(Clone)]
 pub struct MapRegion {
     pub site: Vec2,
     pub vertices: Vec<VoronoiVertex>,
+    pub neighbors: Vec<RegionIndex>,
+    pub elevation: f32,
+    pub moisture: f32,
     pub color: [f32; 3],
 }
 
+impl MapRegion {
+    pub(crate) fn new(
+        site: Vec2,
+        vertices: Vec<VoronoiVertex>,
+        neighbors: Vec<RegionIndex>,
+        elevation: f32,
+        moisture: f32,
+    ) -> Self {
+        Self {
+            site,
+            vertices,
+            neighbors,
+            elevation,
+            moisture,
+            color: get_biome_color(elevation, moisture).into(),
+        }
+    }
+
+    pub fn is_water(&self) -> bool {
+        self.elevation < SEA_LEVEL
+    }
+
+    pub fn biome(&self) -> Biome {
+        Biome::classify(self.elevation, self.moisture)
+    }
+
+    /// Corners of the Voronoi cell in order.
+    pub fn polygon(&self) -> Vec<Vec2> {
+        self.vertices
+            .iter()
+            .step_by(2)
+            .map(|vertex| vertex.position())
+            .collect()
+    }
+
+    /// Voronoi edge shared with `self.neighbors[k]`.
+    pub fn edge(&self, k: usize) -> (Vec2, Vec2) {
+        (
+            self.vertices[2 * k].position(),
+            self.vertices[2 * k + 1].position(),
+        )
+    }
+}
+
 pub fn new_map(
     boundary: Boundary,
     seed: u64,
     distance_fn: DistanceFn,
     reshape_fn: ReshapingFn,
-) -> Map {
-    let points = generate_random_points();
-    let triangulation = init_triangulation_points(points).unwrap();
-    let triangulation = init_triangulation_points(relax_sites(triangulation)).unwrap();
-    let mut regions = extract_voronoi_regions(&triangulation, &boundary);
-
-    let elevation_map = assign_elevation_map(&regions, seed, distance_fn, reshape_fn);
-    let moisture_map = assign_moisture_map(&regions, seed);
-    let mut map_regions = vec![];
-    for (i, region) in regions.into_iter().enumerate() {
-        let color = get_biome_color(elevation_map[i], moisture_map[i]);
-        let mapr = MapRegion {
-            site: region.site,
-            vertices: region.vertices,
-            color: color.into(),
-        };
-        map_regions.push(mapr);
+) -> Result<Map, MapError> {
+    generate_map(MapSettings::new(boundary, seed, distance_fn, reshape_fn))
+}
+
+/// `new_map` reporting to `observer` and stopped by `token`.
+pub fn new_map_with_progress(
+    boundary: Boundary,
+    seed: u64,
+    distance_fn: DistanceFn,
+    reshape_fn: ReshapingFn,
+    observer: &dyn ProgressObserver,
+    token: &CancellationToken,
+) -> Result<Map, MapError> {
+    let settings = MapSettings::new(boundary, seed, distance_fn, reshape_fn);
+    generate_map_with_progress(settings, observer, token)
+}
+
+pub fn generate_map(settings: MapSettings) -> Result<Map, MapError> {
+    generate_tracked(settings, &mut Tracker::silent())
+}
+
+/// `generate_map` reporting every `Stage` to `observer`. `token` stops it between steps of a
+/// stage, the generation then returns `MapError::Cancelled`.
+pub fn generate_map_with_progress(
+    settings: MapSettings,
+    observer: &dyn ProgressObserver,
+    token: &CancellationToken,
+) -> Result<Map, MapError> {
+    generate_tracked(settings, &mut Tracker::new(&Stage::ALL, observer, token))
+}
+
+fn generate_tracked(settings: MapSettings, tracker: &mut Tracker) -> Result<Map, MapError> {
+    if settings.wrap != Wrap::None {
+        return generate_wrapped_map(settings, tracker);
+    }
+    tracker.start(Stage::Sites)?;
+    let points = generate_grid_points(&settings)?;
+    let triangulation = triangulate(points, tracker)?;
+    tracker.finish()?;
+    tracker.start(Stage::Relaxation)?;
+    let relaxed = relax_sites(triangulation, tracker)?;
+    tracker.finish()?;
+    tracker.start(Stage::Triangulation)?;
+    let triangulation = triangulate(relaxed, tracker)?;
+    tracker.finish()?;
+    tracker.start(Stage::Regions)?;
+    let regions = extract_voronoi_regions(&triangulation, &settings.boundary, tracker)?;
+    tracker.finish()?;
+    finish_map(settings, triangulation, regions, vec![], tracker)
+}
+
+/// The sites inside the boundary are triangulated together with copies of the ones near the
+/// opposite edges, so the regions along a wrapping edge get their neighbors from across it.
+fn generate_wrapped_map(settings: MapSettings, tracker: &mut Tracker) -> Result<Map, MapError> {
+    let boundary = &settings.boundary;
+    tracker.start(Stage::Sites)?;
+    let points = generate_grid_points(&settings)?;
+    let count = points.len();
+    let margin = WRAP_MARGIN * cell_size(&settings);
+    let (with_ghosts, _) = add_ghost_sites(points, boundary, settings.wrap, margin);
+    let triangulation = triangulate(with_ghosts, tracker)?;
+    tracker.finish()?;
+    tracker.start(Stage::Relaxation)?;
+    let relaxed: Vec<Vec2> = relax_sites(triangulation, tracker)?
+        .into_iter()
+        .take(count)
+        .map(|site| wrap_point(boundary, settings.wrap, site))
+        .collect();
+    tracker.finish()?;
+    tracker.start(Stage::Triangulation)?;
+    let (with_ghosts, ghosts) = add_ghost_sites(relaxed, boundary, settings.wrap, margin);
+    let triangulation = triangulate(with_ghosts, tracker)?;
+    tracker.finish()?;
+    tracker.start(Stage::Regions)?;
+    let mut regions = extract_voronoi_regions(&triangulation, boundary, tracker)?;
+    tracker.finish()?;
+    regions.truncate(count);
+    for region in &mut regions {
+        for neighbor in &mut region.neighbors {
+            if *neighbor >= count {
+                *neighbor = ghosts[*neighbor - count];
+            }
+        }
     }
+    finish_map(settings, triangulation, regions, ghosts, tracker)
+}
+
+fn finish_map(
+    settings: MapSettings,
+    triangulation: CsTriangulation,
+    regions: Vec<VoronoiRegion>,
+    ghosts: Vec<RegionIndex>,
+    tracker: &mut Tracker,
+) -> Result<Map, MapError> {
+    let sites: Vec<Vec2> = regions.iter().map(|region| region.site).collect();
+    tracker.start(Stage::Elevation)?;
+    let elevation_map = assign_elevation_map(&sites, &settings, tracker)?;
+    tracker.finish()?;
+    tracker.start(Stage::Moisture)?;
+    let moisture_map = assign_moisture_map(&sites, &settings, tracker)?;
+    tracker.finish()?;
+    tracker.start(Stage::Biomes)?;
+    let regions: Vec<_> = regions
+        .into_iter()
+        .zip(elevation_map.into_iter().zip(moisture_map))
+        .collect();
+    let map_regions = tracker.map_vec(regions, |(region, (elevation, moisture))| {
+        MapRegion::new(
+            region.site,
+            region.vertices,
+            region.neighbors,
+            elevation,
+            moisture,
+        )
+    })?;
+    tracker.finish()?;
 
-    Map {
-        triangulation,
-        boundary,
+    Ok(Map {
+        triangulation: Arc::new(triangulation),
+        ghosts: Arc::new(ghosts),
+        settings,
         regions: map_regions,
-    }
+    })
 }
 
-fn generate_random_points() -> Vec<Vec2> {
-    let GRID_SIZE = 66;
-    let HALF_GRID = GRID_SIZE / 2;
-    let JITTER = 0.5f32;
+/// Map over exactly `points`, without relaxation, where `terrain` gives the elevation and
+/// moisture of each site. Sites keep the order of `points`.
+pub(crate) fn build_map<F>(
+    settings: MapSettings,
+    points: Vec<Vec2>,
+    terrain: F,
+) -> Result<Map, MapError>
+where
+    F: Fn(Vec2) -> (f32, f32) + Sync + Send,
+{
+    let triangulation = init_triangulation_points(points)?;
+    let regions = extract_voronoi_regions(&triangulation, &settings.boundary, &Tracker::silent())?;
+    let map_regions = crate::parallel::map_vec(regions, |region| {
+        let (elevation, moisture) = terrain(region.site);
+        MapRegion::new(
+            region.site,
+            region.vertices,
+            region.neighbors,
+            elevation,
+            moisture,
+        )
+    });
+    Ok(Map {
+        triangulation: Arc::new(triangulation),
+        ghosts: Arc::new(vec![]),
+        settings,
+        regions: map_regions,
+    })
+}
+
+/// Jittered grid with square cells of about one unit, or of the size giving `site_count` cells.
+/// It covers the boundary exactly along the wrapping axes and goes two rows past it along the
+/// others. The cells of the outer row are left open by the triangulation hull, the ones of the
+/// inner row are closed and cover the boundary.
+fn generate_grid_points(settings: &MapSettings) -> Result<Vec<Vec2>, MapError> {
+    let jitter = 0.5f32;
+    let (boundary, wrap) = (&settings.boundary, settings.wrap);
+    if boundary.width() <= 0. || boundary.height() <= 0. {
+        return Err(MapError::DegenerateSites);
+    }
+    let cell = cell_size(settings);
+    let bottom_left = boundary.bottom_left();
+    let columns = (boundary.width() / cell).round().max(1.) as i32;
+    let rows = (boundary.height() / cell).round().max(1.) as i32;
+    let cell_width = boundary.width() / columns as f32;
+    let cell_height = boundary.height() / rows as f32;
+    if (wrap.horizontal() && columns < MIN_WRAPPED_CELLS)
+        || (wrap.vertical() && rows < MIN_WRAPPED_CELLS)
+    {
+        return Err(MapError::TooNarrow);
+    }
+    let extra_columns = if wrap.horizontal() { 0 } else { 2 };
+    let extra_rows = if wrap.vertical() { 0 } else { 2 };
 
+    let mut rng = StdRng::seed_from_u64(settings.site_seed);
     let mut points = vec![];
+    for x in -extra_columns..columns + extra_columns {
+        for y in -extra_rows..rows + extra_rows {
+            let x_displace = jitter * (rng.gen::<f32>() - rng.gen::<f32>());
+            let y_displace = jitter * (rng.gen::<f32>() - rng.gen::<f32>());
+            points.push(Vec2::new(
+                bottom_left.x + (x as f32 + 0.5 + x_displace) * cell_width,
+                bottom_left.y + (y as f32 + 0.5 + y_displace) * cell_height,
+            ));
+        }
+    }
+    Ok(points)
+}
 
-    for x in -HALF_GRID..HALF_GRID {
-        for y in -HALF_GRID..HALF_GRID {
-            let x = x as f32;
-            let y = y as f32;
-            let x_displace = JITTER * (rand::random::<f32>() - rand::random::<f32>());
-            let y_displace = JITTER * (rand::random::<f32>() - rand::random::<f32>());
-            points.push(Vec2::new(x + x_displace, y + y_displace));
+/// Side of the square cells `generate_grid_points` aims for.
+fn cell_size(settings: &MapSettings) -> f32 {
+    let boundary = &settings.boundary;
+    match settings.site_count {
+        Some(count) => (boundary.width() * boundary.height() / count.max(1) as f32).sqrt(),
+        None => 1.,
+    }
+}
+
+/// Appends the copies of `points` that land within `margin` of the boundary once shifted
+/// across a wrapping edge, along with the index of the point each copy comes from.
+fn add_ghost_sites(
+    mut points: Vec<Vec2>,
+    boundary: &Boundary,
+    wrap: Wrap,
+    margin: f32,
+) -> (Vec<Vec2>, Vec<RegionIndex>) {
+    let (width, height) = (boundary.width(), boundary.height());
+    let bottom_left = boundary.bottom_left();
+    let top_right = boundary.top_right();
+    let xs: &[f32] = if wrap.horizontal() {
+        &[-1., 0., 1.]
+    } else {
+        &[0.]
+    };
+    let ys: &[f32] = if wrap.vertical() {
+        &[-1., 0., 1.]
+    } else {
+        &[0.]
+    };
+    let mut ghosts = vec![];
+    let count = points.len();
+    for &dy in ys {
+        for &dx in xs {
+            if dx == 0. && dy == 0. {
+                continue;
+            }
+            for i in 0..count {
+                let ghost = points[i] + Vec2::new(dx * width, dy * height);
+                if ghost.x > bottom_left.x - margin
+                    && ghost.x < top_right.x + margin
+                    && ghost.y > bottom_left.y - margin
+                    && ghost.y < top_right.y + margin
+                {
+                    points.push(ghost);
+                    ghosts.push(i);
+                }
+            }
         }
     }
-    points
+    (points, ghosts)
+}
+
+fn wrap_point(boundary: &Boundary, wrap: Wrap, point: Vec2) -> Vec2 {
+    let bottom_left = boundary.bottom_left();
+    let mut point = point;
+    if wrap.horizontal() {
+        point.x = bottom_left.x + (point.x - bottom_left.x).rem_euclid(boundary.width());
+    }
+    if wrap.vertical() {
+        point.y = bottom_left.y + (point.y - bottom_left.y).rem_euclid(boundary.height());
+    }
+    point
 }
 
 fn init_triangulation_points(points: Vec<Vec2>) -> Result<CsTriangulation, InsertionError> {
@@ -98,9 +748,24 @@ fn init_triangulation_points(points: Vec<Vec2>) -> Result<CsTriangulation, Inser
     Ok(result)
 }
 
-fn relax_sites(triangulation: CsTriangulation) -> Vec<Vec2> {
-    let mut relaxed_sites = vec![];
-    for site in triangulation.vertices() {
+/// `init_triangulation_points` checking for cancellation as the points go in, and that they
+/// make at least one triangle.
+fn triangulate(points: Vec<Vec2>, tracker: &Tracker) -> Result<CsTriangulation, MapError> {
+    let mut result = CsTriangulation::new();
+    let count = points.len();
+    for (i, pt) in points.into_iter().enumerate() {
+        tracker.step(i, count)?;
+        result.insert(VertexType::new(pt.x, pt.y))?;
+    }
+    if result.num_inner_faces() == 0 {
+        return Err(MapError::DegenerateSites);
+    }
+    Ok(result)
+}
+
+fn relax_sites(triangulation: CsTriangulation, tracker: &Tracker) -> Result<Vec<Vec2>, MapError> {
+    tracker.map_indices(triangulation.num_vertices(), |i| {
+        let site = triangulation.vertex(FixedVertexHandle::from_index(i));
         let region = site.as_voronoi_face();
         let mut region_vertices = vec![];
         let mut skip_relax = false;
@@ -139,22 +804,22 @@ fn relax_sites(triangulation: CsTriangulation) -> Vec<Vec2> {
             let mut sum_x = 0.;
             let mut sum_y = 0.;
             let vertice_count = region_vertices.len() as f32;
-            for i in 0..region_vertices.len() {
-                sum_x += region_vertices[i].x;
-                sum_y += region_vertices[i].y;
+            for vertex in &region_vertices {
+                sum_x += vertex.x;
+                sum_y += vertex.y;
             }
-            relaxed_sites.push(Vec2::new(sum_x / vertice_count, sum_y / vertice_count));
+            Vec2::new(sum_x / vertice_count, sum_y / vertice_count)
         } else {
-            relaxed_sites.push(Vec2::new(site.position().x, site.position().y));
+            Vec2::new(site.position().x, site.position().y)
         }
-    }
-    relaxed_sites
+    })
 }
 
 fn extract_voronoi_regions(
     triangulation: &CsTriangulation,
     boundary: &Boundary,
-) -> Vec<VoronoiRegion> {
+    tracker: &Tracker,
+) -> Result<Vec<VoronoiRegion>, MapError> {
     let (upper, lower) = {
         let upper = boundary.top_right();
         let upper = spade::Point2::new(upper.x, upper.y);
@@ -162,28 +827,18 @@ fn extract_voronoi_regions(
         let lower = spade::Point2::new(lower.x, lower.y);
         (upper, lower)
     };
-    let mut regions = vec![];
-    let mut corners = BTreeSet::new();
-    // let mut regions_neighbors = HashMap::new();
-
     // for vertex in triangulation.get_vertices_in_rectangle(lower, upper) {
-    for vertex in triangulation.vertices() {
+    tracker.map_indices(triangulation.num_vertices(), |i| {
+        let vertex = triangulation.vertex(FixedVertexHandle::from_index(i));
         let region_site = vertex.data().position;
 
         let region = vertex.as_voronoi_face();
         let mut region_vertices = vec![];
-        // let mut neighbors = vec![];
+        let mut neighbors = vec![];
 
         for edge in vertex.out_edges() {
             let voronoi_edge = edge.as_voronoi_edge();
-            let neighbor = edge.to().position();
-            // {
-            //     let idx = edge.to().index();
-            //     let idx_find = triangulation.locate_vertex(neighbor).unwrap().index();
-            //     if idx != idx_find {
-            //         println!("{} != {}", idx, idx_find);
-            //     }
-            // }
+            let neighbor = edge.to().fix().index();
             match [voronoi_edge.from(), voronoi_edge.to()] {
                 [Inner(from), Inner(to)] => {
                     let from = from.circumcenter();
@@ -193,12 +848,10 @@ fn extract_voronoi_regions(
                     // if dir.norm_squared() > 4.0 {
                     //     println!("inner/inner length > 2");
                     // }
-                    // let neighbor = edge.to().position();
 
-                    corners.insert(VoronoiCorner::new(from.x, from.y));
-                    corners.insert(VoronoiCorner::new(to.x, to.y));
                     region_vertices.push(VoronoiVertex::Inner(Vec2::new(to.x, to.y)));
                     region_vertices.push(VoronoiVertex::Inner(Vec2::new(from.x, from.y)));
+                    neighbors.push(neighbor);
                 }
                 [Inner(from), Outer(edge)] | [Outer(edge), Inner(from)] => {
                     let from = from.circumcenter();
@@ -213,74 +866,97 @@ fn extract_voronoi_regions(
                     };
                     let outer_pt = from + dir;
 
-                    corners.insert(VoronoiCorner::new(from.x, from.y));
-                    corners.insert(VoronoiCorner::new(outer_pt.x, outer_pt.y));
                     region_vertices.push(VoronoiVertex::Inner(outer_pt));
                     region_vertices.push(VoronoiVertex::Inner(Vec2::new(from.x, from.y)));
+                    neighbors.push(neighbor);
                 }
                 [_, _] => {}
             };
         }
-        // regions_neighbors.insert(regions.len(), neighbors);
-        regions.push(VoronoiRegion::new(
+        VoronoiRegion::new(
             Vec2::new(region_site.x, region_site.y),
             region_vertices,
-        ));
-    }
-    println!("count corners: {}", corners.len());
-    regions
+            neighbors,
+        )
+    })
 }
 
-fn assign_elevation_map(
-    regions: &Vec<VoronoiRegion>,
-    seed: u64,
-    distance_fn: DistanceFn,
-    reshape_fn: ReshapingFn,
-) -> Vec<f32> {
+pub(crate) fn terrain_noise(seed: u64) -> FastNoise {
     let mut noise = FastNoise::seeded(seed);
-    let GRID_SIZE = 64.;
     noise.set_noise_type(NoiseType::Simplex);
     noise.set_fractal_type(FractalType::FBM);
     noise.set_fractal_octaves(5);
     noise.set_fractal_gain(0.5);
     noise.set_fractal_lacunarity(0.5);
     noise.set_frequency(2.0);
-    let mut elevation_map = vec![];
-    for (i, region) in regions.iter().enumerate() {
-        let nx = region.site().x / GRID_SIZE;
-        let ny = region.site().y / GRID_SIZE;
+    noise
+}
 
-        let n = noise.get_noise(nx, ny);
+fn assign_elevation_map(
+    sites: &[Vec2],
+    settings: &MapSettings,
+    tracker: &Tracker,
+) -> Result<Vec<f32>, MapError> {
+    let noise = terrain_noise(settings.seed);
+    let GRID_SIZE = 64.;
+    let wrap = settings.wrap;
+    tracker.map_indices(sites.len(), |i| {
+        let nx = sites[i].x / GRID_SIZE;
+        let ny = sites[i].y / GRID_SIZE;
+
+        let n = sample_noise(&noise, &settings.boundary, wrap, sites[i], GRID_SIZE);
         let elevation = 1. + n;
         // let d = 2. * nx.abs().max(ny.abs());
-        let d = distance_fn.apply(nx, ny);
-        elevation_map.push((1. + elevation - 1.5 * reshape_fn.apply(d, elevation)) / 2.);
-    }
-    elevation_map
+        // the island falloff is only kept along the edges that don't wrap
+        let d = settings.distance_fn.apply(
+            if wrap.horizontal() { 0. } else { nx },
+            if wrap.vertical() { 0. } else { ny },
+        );
+        (1. + elevation - 1.5 * settings.reshape_fn.apply(d, elevation)) / 2.
+    })
 }
 
-fn assign_moisture_map(regions: &Vec<VoronoiRegion>, seed: u64) -> Vec<f32> {
-    let mut noise = FastNoise::seeded(seed);
+fn assign_moisture_map(
+    sites: &[Vec2],
+    settings: &MapSettings,
+    tracker: &Tracker,
+) -> Result<Vec<f32>, MapError> {
+    let noise = terrain_noise(settings.seed);
     let GRID_SIZE = 64.;
-    noise.set_noise_type(NoiseType::Simplex);
-    noise.set_fractal_type(FractalType::FBM);
-    noise.set_fractal_octaves(5);
-    noise.set_fractal_gain(0.5);
-    noise.set_fractal_lacunarity(0.5);
-    noise.set_frequency(2.0);
-    let mut moisture_map = vec![];
-    for (i, region) in regions.iter().enumerate() {
-        let nx = region.site().x / GRID_SIZE;
-        let ny = region.site().y / GRID_SIZE;
+    tracker.map_indices(sites.len(), |i| {
+        let n = sample_noise(
+            &noise,
+            &settings.boundary,
+            settings.wrap,
+            sites[i],
+            GRID_SIZE,
+        );
+        (1. - n) / 2.
+    })
+}
 
-        let n = noise.get_noise(nx, ny);
-        let m = (1. - n) / 2.;
-        moisture_map.push(m);
+/// Noise at `site`, periodic along the wrapping axes. Horizontal wrapping samples a cylinder of
+/// the same circumference as the boundary, vertical wrapping then blends the cylinder with
+/// itself shifted by one height.
+fn sample_noise(noise: &FastNoise, boundary: &Boundary, wrap: Wrap, site: Vec2, scale: f32) -> f32 {
+    if !wrap.horizontal() {
+        return noise.get_noise(site.x / scale, site.y / scale);
     }
-    moisture_map
+    let bottom_left = boundary.bottom_left();
+    let radius = boundary.width() / (2. * PI * scale);
+    let angle = 2. * PI * (site.x - bottom_left.x) / boundary.width();
+    let cylinder =
+        |y: f32| noise.get_noise3d(radius * angle.cos(), radius * angle.sin(), y / scale);
+    if !wrap.vertical() {
+        return cylinder(site.y);
+    }
+    let t = ((site.y - bottom_left.y) / boundary.height()).clamp(0., 1.);
+    let blended = (1. - t) * cylinder(site.y) + t * cylinder(site.y - boundary.height());
+    // blending two samples flattens the middle rows, scale them back to the usual contrast
+    blended / ((1. - t).powi(2) + t * t).sqrt()
 }
 
-#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Hash)]
 pub enum Biome {
     Abyss,
     Ocean,
@@ -294,6 +970,19 @@ pub enum Biome {
     Volcan,
 }
 impl Biome {
+    pub const ALL: [Biome; 10] = [
+        Biome::Abyss,
+        Biome::Ocean,
+        Biome::Coast,
+        Biome::Beach,
+        Biome::Mountain,
+        Biome::SnowyMountain,
+        Biome::GrassLand,
+        Biome::Desert,
+        Biome::Forest,
+        Biome::Volcan,
+    ];
+
     pub fn from(e: f32, m: f32) -> Self {
         if e < 0.08 {
             Self::Ocean
@@ -320,6 +1009,37 @@ impl Biome {
         }
     }
 
+    /// Same as `from` but on the map's elevation scale, where `SEA_LEVEL` splits water and land
+    /// exactly like the color gradient does.
+    pub fn classify(elevation: f32, moisture: f32) -> Self {
+        let e = (elevation - SEA_LEVEL) * 2.;
+        if e < -0.6 {
+            Self::Abyss
+        } else if e < -0.1 {
+            Self::Ocean
+        } else if e < 0. {
+            Self::Coast
+        } else if e < 0.05 {
+            Self::Beach
+        } else if e > 0.6 {
+            if moisture > 0.6 {
+                Self::SnowyMountain
+            } else {
+                Self::Mountain
+            }
+        } else if moisture < 0.3 {
+            Self::Desert
+        } else if moisture < 0.6 {
+            Self::GrassLand
+        } else {
+            Self::Forest
+        }
+    }
+
+    pub fn is_mountain(&self) -> bool {
+        matches!(self, Biome::Mountain | Biome::SnowyMountain | Biome::Volcan)
+    }
+
     pub fn to_color(&self) -> RGB {
         match self {
             Biome::Abyss => RGB::new(10, 11, 37),
@@ -336,9 +1056,9 @@ impl Biome {
     }
 }
 
-fn get_biome_color(elevation: f32, moisture: f32) -> RGB {
+pub(crate) fn get_biome_color(elevation: f32, moisture: f32) -> RGB {
     // return Biome::from(elevation, moisture).to_color();
-    let elevation = (elevation - 0.5) * 2.;
+    let elevation = (elevation - SEA_LEVEL) * 2.;
     if elevation < 0. {
         let r = 48. + 48. * elevation;
         let g = 64. + 64. * elevation;
@@ -360,7 +1080,7 @@ fn get_biome_color(elevation: f32, moisture: f32) -> RGB {
     }
 }
 
-#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Serialize, Deserialize)]
 pub enum DistanceFn {
     Euclidean,
     Euclidean2,
@@ -388,7 +1108,7 @@ impl DistanceFn {
     }
 }
 
-#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Serialize, Deserialize)]
 pub enum ReshapingFn {
     Input,
     Flat,
@@ -445,3 +1165,280 @@ impl ReshapingFn {
 pub fn bezier3(p0: f32, p1: f32, p2: f32, t: f32) -> f32 {
     p1 + (1.0 - t).powi(2) * (p0 - p1) + t.powi(2) * (p2 - p1)
 }
+
+/// Settings of the 64 x 64 island most tests run on.
+#[cfg(test)]
+pub(crate) fn test_settings() -> MapSettings {
+    let boundary = Boundary::from_top_left(Vec2::new(-32.0, 32.0), 64., 64.);
+    MapSettings::new(boundary, 12345, DistanceFn::Diagonal, ReshapingFn::Flat)
+}
+
+/// The test island, wrapping along `wrap`.
+#[cfg(test)]
+pub(crate) fn test_map(wrap: Wrap) -> Map {
+    generate_map(test_settings().with_wrap(wrap)).unwrap()
+}
+
+#[cfg(test)]
+mod map_tests {
+    use super::*;
+
+    #[test]
+    fn test_polygon_surrounds_site() {
+        let map = test_map(Wrap::None);
+        for region in map.get_regions() {
+            if region.site.x.abs() > 30. || region.site.y.abs() > 30. {
+                continue;
+            }
+            let polygon = region.polygon();
+            assert_eq!(polygon.len(), region.neighbors.len());
+            for k in 0..polygon.len() {
+                let (a, b) = (polygon[k], polygon[(k + 1) % polygon.len()]);
+                assert!((region.vertices[2 * k + 1].position() - b).norm() < 1e-4);
+                // same side of every edge
+                let cross = (b - a).perp(&(region.site - a));
+                assert!(cross * (polygon[1] - polygon[0]).perp(&(region.site - polygon[0])) > 0.);
+            }
+        }
+    }
+
+    #[test]
+    fn test_elevation_at_sites() {
+        let map = test_map(Wrap::None);
+        for region in map.get_regions().iter().step_by(17) {
+            if let Some(elevation) = map.elevation_at(region.site) {
+                assert!((elevation - region.elevation).abs() < 1e-4);
+            }
+        }
+        assert_eq!(map.elevation_at(Vec2::new(40., 0.)), None);
+        let grid = map.elevation_grid(16, 8);
+        assert_eq!(grid.len(), 16 * 8);
+        let lowest = map
+            .get_regions()
+            .iter()
+            .map(|r| r.elevation)
+            .fold(1., f32::min);
+        assert!(grid.iter().all(|&e| e >= lowest - 1e-4));
+    }
+
+    #[test]
+    fn test_region_at_site() {
+        let map = test_map(Wrap::None);
+        for (i, region) in map.get_regions().iter().enumerate() {
+            if map.get_boundary().point_inside(region.site) {
+                assert_eq!(map.region_at(region.site), Some(i));
+            }
+        }
+    }
+
+    #[test]
+    fn test_region_at_outside_boundary() {
+        let map = test_map(Wrap::None);
+        assert_eq!(map.region_at(Vec2::new(-32.5, 0.)), None);
+        assert_eq!(map.region_at(Vec2::new(0., 40.)), None);
+        assert!(map.region_at(Vec2::new(-32., 32.)).is_some());
+    }
+
+    #[test]
+    fn test_wrapped_neighbors_cross_the_seam() {
+        let map = test_map(Wrap::Both);
+        let regions = map.get_regions();
+        let mut crossing = 0;
+        for (i, region) in regions.iter().enumerate() {
+            assert!(map.get_boundary().point_inside(region.site));
+            for &neighbor in &region.neighbors {
+                assert!(regions[neighbor].neighbors.contains(&i));
+                let dx = (regions[neighbor].site.x - region.site.x).abs();
+                let dy = (regions[neighbor].site.y - region.site.y).abs();
+                if dx > 32. || dy > 32. {
+                    crossing += 1;
+                }
+            }
+        }
+        // every region of the four edges has at least one neighbor on the other side
+        assert!(crossing >= 4 * 64);
+    }
+
+    #[test]
+    fn test_wrapped_region_at() {
+        let map = test_map(Wrap::Horizontal);
+        let inside = map.region_at(Vec2::new(-31.8, 3.)).unwrap();
+        assert_eq!(map.region_at(Vec2::new(32.2, 3.)), Some(inside));
+        assert_eq!(map.region_at(Vec2::new(0., 40.)), None);
+        assert_eq!(map.tile_offsets().len(), 3);
+        assert_eq!(test_map(Wrap::Both).tile_offsets().len(), 9);
+    }
+
+    #[test]
+    fn test_wrapped_noise_is_periodic() {
+        let boundary = Boundary::from_top_left(Vec2::new(-32.0, 32.0), 64., 64.);
+        let noise = terrain_noise(7);
+        for i in 0..16 {
+            let site = Vec2::new(-32. + i as f32 * 0.37, -32. + i as f32 * 0.91);
+            let a = sample_noise(&noise, &boundary, Wrap::Both, site, 64.);
+            let b = sample_noise(
+                &noise,
+                &boundary,
+                Wrap::Both,
+                site + Vec2::new(64., 0.),
+                64.,
+            );
+            assert!((a - b).abs() < 1e-4);
+        }
+        let bottom = sample_noise(&noise, &boundary, Wrap::Both, Vec2::new(5., -32.), 64.);
+        let top = sample_noise(&noise, &boundary, Wrap::Both, Vec2::new(5., 32.), 64.);
+        assert!((bottom - top).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_same_seed_same_map() {
+        let a = test_map(Wrap::None);
+        let b = test_map(Wrap::None);
+        assert!(!a.shares_sites_with(&b));
+        for (ra, rb) in a.get_regions().iter().zip(b.get_regions()) {
+            assert_eq!(ra.site, rb.site);
+            assert_eq!(ra.elevation, rb.elevation);
+        }
+    }
+
+    #[test]
+    fn test_regenerate_reuses_sites() {
+        let map = test_map(Wrap::None);
+        let reshaped = map
+            .regenerate(map.get_seed(), DistanceFn::Euclidean, ReshapingFn::Smooth)
+            .unwrap();
+        assert!(reshaped.shares_sites_with(&map));
+        let fresh = generate_map(reshaped.get_settings().clone()).unwrap();
+        for (a, b) in reshaped.get_regions().iter().zip(fresh.get_regions()) {
+            assert_eq!(a.site, b.site);
+            assert_eq!(a.neighbors, b.neighbors);
+            assert_eq!(a.elevation, b.elevation);
+            assert_eq!(a.moisture, b.moisture);
+            assert_eq!(a.color, b.color);
+        }
+
+        let reseeded = map
+            .regenerate(7, DistanceFn::Diagonal, ReshapingFn::Flat)
+            .unwrap();
+        assert!(reseeded.shares_sites_with(&map));
+        assert!(map
+            .get_regions()
+            .iter()
+            .zip(reseeded.get_regions())
+            .any(|(a, b)| a.moisture != b.moisture));
+    }
+
+    #[test]
+    fn test_site_count() {
+        let boundary = Boundary::from_top_left(Vec2::new(0., 40.), 80., 40.);
+        let settings =
+            MapSettings::new(boundary.clone(), 3, DistanceFn::Diagonal, ReshapingFn::Flat)
+                .with_site_count(800);
+        let map = generate_map(settings).unwrap();
+        // 40 x 20 cells plus the two rows around them
+        assert_eq!(map.get_regions().len(), 44 * 24);
+        assert!(map.region_at(Vec2::new(79., 1.)).is_some());
+        let sparse = map
+            .regenerate_with(map.get_settings().clone().with_site_count(200))
+            .unwrap();
+        assert_eq!(sparse.get_regions().len(), 24 * 14);
+    }
+
+    #[test]
+    fn test_narrow_wrapping() {
+        let narrow = |width: f32, wrap: Wrap| {
+            let boundary = Boundary::from_top_left(Vec2::new(0., 64.), width, 64.);
+            MapSettings::new(boundary, 5, DistanceFn::Diagonal, ReshapingFn::Flat).with_wrap(wrap)
+        };
+        assert!(matches!(
+            generate_map(narrow(2., Wrap::Both)),
+            Err(MapError::TooNarrow)
+        ));
+        assert!(matches!(
+            generate_map(narrow(8., Wrap::Horizontal).with_site_count(8)),
+            Err(MapError::TooNarrow)
+        ));
+        // only the wrapping axes need the cells
+        generate_map(narrow(2., Wrap::None))
+            .unwrap()
+            .validate()
+            .assert_valid();
+        let map = generate_map(narrow(4., Wrap::Both)).unwrap();
+        map.validate().assert_valid();
+        for (i, region) in map.get_regions().iter().enumerate() {
+            assert!(!region.neighbors.contains(&i));
+        }
+    }
+
+    #[test]
+    fn test_new_sites_rebuild_diagram() {
+        let map = test_map(Wrap::None);
+        let settings = MapSettings {
+            site_seed: 99,
+            ..map.get_settings().clone()
+        };
+        let moved = map.regenerate_with(settings).unwrap();
+        assert!(!moved.shares_sites_with(&map));
+        assert_ne!(moved.get_regions()[0].site, map.get_regions()[0].site);
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn test_parallel_matches_single_thread() {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(1)
+            .build()
+            .unwrap();
+        let serial = pool.install(|| test_map(Wrap::None));
+        let parallel = test_map(Wrap::None);
+        for (a, b) in serial.get_regions().iter().zip(parallel.get_regions()) {
+            assert_eq!(a.site, b.site);
+            assert_eq!(a.neighbors, b.neighbors);
+            assert_eq!(a.elevation.to_bits(), b.elevation.to_bits());
+            assert_eq!(a.moisture.to_bits(), b.moisture.to_bits());
+            assert_eq!(a.color, b.color);
+            for (va, vb) in a.vertices.iter().zip(&b.vertices) {
+                assert_eq!(va.position(), vb.position());
+            }
+        }
+    }
+
+    #[test]
+    fn test_same_map_with_and_without_parallel() {
+        // recorded from a build without the `parallel` feature, both builds have to match it
+        assert_eq!(test_map(Wrap::None).content_hash(), 0x7e8eebc15c38ae2e);
+        assert_eq!(test_map(Wrap::Both).content_hash(), 0x46010ae21e831654);
+    }
+
+    #[test]
+    fn test_region_grid() {
+        let map = test_map(Wrap::None);
+        let grid = map.region_grid(16, 8);
+        assert_eq!(grid.len(), 16 * 8);
+        assert!(grid.iter().all(|region| region.is_some()));
+        let top_left = map.region_at(Vec2::new(-30., 28.));
+        assert_eq!(grid[0], top_left);
+    }
+
+    #[test]
+    fn test_degenerate_sites() {
+        let line = (0..10)
+            .map(|i| Vec2::new(i as f32, 2. * i as f32))
+            .collect();
+        let result = triangulate(line, &Tracker::silent());
+        assert!(matches!(result, Err(MapError::DegenerateSites)));
+
+        let settings = |boundary| {
+            MapSettings::new(boundary, 1, DistanceFn::Euclidean, ReshapingFn::Linear)
+                .with_site_count(100)
+        };
+        let flat = Boundary::from_top_left(Vec2::new(0., 0.), 20., 0.);
+        for wrap in [Wrap::None, Wrap::Both] {
+            let result = generate_map(settings(flat.clone()).with_wrap(wrap));
+            assert!(matches!(result, Err(MapError::DegenerateSites)));
+        }
+        let broken = Boundary::from_top_left(Vec2::new(f32::NAN, 0.), 20., 20.);
+        let result = generate_map(settings(broken));
+        assert!(matches!(result, Err(MapError::Triangulation(_))));
+    }
+}
@@ -0,0 +1,193 @@
+use std::collections::HashMap;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::map::RegionIndex;
+
+const START: char = '^';
+const END: char = '$';
+const MAX_ATTEMPTS: usize = 32;
+
+/// Something on the map that can carry a name.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum Feature {
+    Region(RegionIndex),
+    Landmass(usize),
+    Lake(usize),
+    Settlement(usize),
+}
+
+impl Feature {
+    fn key(&self) -> u64 {
+        let (kind, index) = match self {
+            Feature::Region(i) => (0u64, *i),
+            Feature::Landmass(i) => (1, *i),
+            Feature::Lake(i) => (2, *i),
+            Feature::Settlement(i) => (3, *i),
+        };
+        (kind << 56) ^ index as u64
+    }
+}
+
+/// Character level Markov chain trained from the word list of one culture.
+pub struct NameGenerator {
+    order: usize,
+    min_length: usize,
+    max_length: usize,
+    transitions: HashMap<String, Vec<char>>,
+}
+
+impl NameGenerator {
+    pub fn new<'a>(words: impl IntoIterator<Item = &'a str>, order: usize) -> Self {
+        let order = order.max(1);
+        let mut transitions: HashMap<String, Vec<char>> = HashMap::new();
+        let mut min_length = usize::MAX;
+        let mut max_length = 0;
+        for word in words {
+            let word = word.trim().to_lowercase();
+            if word.is_empty() {
+                continue;
+            }
+            let len = word.chars().count();
+            min_length = min_length.min(len);
+            max_length = max_length.max(len);
+
+            let mut chars = vec![START; order];
+            chars.extend(word.chars());
+            chars.push(END);
+            for window in chars.windows(order + 1) {
+                let prefix: String = window[..order].iter().collect();
+                transitions.entry(prefix).or_default().push(window[order]);
+            }
+        }
+        Self {
+            order,
+            min_length: min_length.min(max_length),
+            max_length,
+            transitions,
+        }
+    }
+
+    pub fn with_length(mut self, min_length: usize, max_length: usize) -> Self {
+        self.min_length = min_length;
+        self.max_length = max_length.max(min_length);
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.transitions.is_empty()
+    }
+
+    pub fn generate<R: Rng>(&self, rng: &mut R) -> String {
+        if self.is_empty() {
+            return String::new();
+        }
+        let mut best = String::new();
+        for _ in 0..MAX_ATTEMPTS {
+            let name = self.walk(rng);
+            let len = name.chars().count();
+            if len >= self.min_length && len <= self.max_length {
+                return capitalize(&name);
+            }
+            if len > best.chars().count() {
+                best = name;
+            }
+        }
+        capitalize(&best)
+    }
+
+    /// Same `seed` and `feature` always give the same name.
+    pub fn name_for(&self, seed: u64, feature: Feature) -> String {
+        let mut rng = StdRng::seed_from_u64(splitmix64(seed ^ splitmix64(feature.key())));
+        self.generate(&mut rng)
+    }
+
+    fn walk<R: Rng>(&self, rng: &mut R) -> String {
+        let mut state: Vec<char> = vec![START; self.order];
+        let mut name = String::new();
+        while name.chars().count() < self.max_length {
+            let prefix: String = state[state.len() - self.order..].iter().collect();
+            let next = match self.transitions.get(&prefix) {
+                Some(candidates) => candidates[rng.gen_range(0..candidates.len())],
+                None => break,
+            };
+            if next == END {
+                break;
+            }
+            name.push(next);
+            state.push(next);
+        }
+        name
+    }
+}
+
+fn capitalize(name: &str) -> String {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+pub(crate) fn splitmix64(value: u64) -> u64 {
+    let mut z = value.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod names_tests {
+    use super::*;
+
+    const ELVISH: [&str; 12] = [
+        "aerendil",
+        "galadriel",
+        "elrond",
+        "celeborn",
+        "arwen",
+        "legolas",
+        "thranduil",
+        "haldir",
+        "earendil",
+        "luthien",
+        "finrod",
+        "glorfindel",
+    ];
+
+    #[test]
+    fn test_same_seed_same_name() {
+        let names = NameGenerator::new(ELVISH, 2);
+        let a = names.name_for(42, Feature::Region(7));
+        let b = names.name_for(42, Feature::Region(7));
+        assert_eq!(a, b);
+        assert!(!a.is_empty());
+    }
+
+    #[test]
+    fn test_names_use_trained_letters() {
+        let names = NameGenerator::new(ELVISH, 2);
+        let alphabet: String = ELVISH.concat();
+        for i in 0..50 {
+            let name = names.name_for(1, Feature::Settlement(i)).to_lowercase();
+            assert!(name.chars().all(|c| alphabet.contains(c)), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_features_are_distinct() {
+        let names = NameGenerator::new(ELVISH, 2);
+        let distinct: std::collections::HashSet<String> = (0..20)
+            .map(|i| names.name_for(3, Feature::Region(i)))
+            .collect();
+        assert!(distinct.len() > 1);
+    }
+
+    #[test]
+    fn test_empty_word_list() {
+        let names = NameGenerator::new([], 3);
+        assert!(names.is_empty());
+        assert_eq!(names.name_for(1, Feature::Lake(0)), "");
+    }
+}
@@ -0,0 +1,433 @@
+use std::f32::consts::{PI, TAU};
+
+use bracket_noise::prelude::FastNoise;
+use nalgebra_glm::{Vec2, Vec3};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use spade::{DelaunayTriangulation, Point2, Triangulation};
+
+use crate::map::{get_biome_color, terrain_noise, Biome, RegionIndex, SEA_LEVEL};
+use crate::parallel::map_indices;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum SiteDistribution {
+    /// Golden angle spiral, nudged by `PlanetSettings::jitter`.
+    Fibonacci,
+    /// Uniform random points moved once to the centroid of their region.
+    RelaxedRandom,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlanetSettings {
+    pub seed: u64,
+    pub site_count: usize,
+    pub distribution: SiteDistribution,
+    /// Fraction of the average site spacing the Fibonacci sites are moved by.
+    pub jitter: f32,
+    /// Multiplies the unit sphere coordinates before sampling the noise.
+    pub noise_scale: f32,
+}
+
+impl Default for PlanetSettings {
+    fn default() -> Self {
+        Self {
+            seed: 12345,
+            site_count: 4096,
+            distribution: SiteDistribution::Fibonacci,
+            jitter: 0.3,
+            noise_scale: 1.0,
+        }
+    }
+}
+
+/// Same as `MapRegion` but on the unit sphere.
+#[derive(Clone)]
+pub struct PlanetRegion {
+    pub site: Vec3,
+    /// Voronoi corners counter clockwise seen from outside, the edge from `corners[k]` to
+    /// `corners[k + 1]` is shared with `neighbors[k]`.
+    pub corners: Vec<Vec3>,
+    pub neighbors: Vec<RegionIndex>,
+    pub elevation: f32,
+    pub moisture: f32,
+    pub color: [f32; 3],
+}
+
+impl PlanetRegion {
+    pub fn is_water(&self) -> bool {
+        self.elevation < SEA_LEVEL
+    }
+
+    pub fn biome(&self) -> Biome {
+        Biome::classify(self.elevation, self.moisture)
+    }
+
+    /// Voronoi edge shared with `self.neighbors[k]`.
+    pub fn edge(&self, k: usize) -> (Vec3, Vec3) {
+        (self.corners[k], self.corners[(k + 1) % self.corners.len()])
+    }
+}
+
+pub struct Planet {
+    settings: PlanetSettings,
+    regions: Vec<PlanetRegion>,
+}
+
+impl Planet {
+    pub fn get_regions(&self) -> &Vec<PlanetRegion> {
+        &self.regions
+    }
+
+    pub fn get_settings(&self) -> &PlanetSettings {
+        &self.settings
+    }
+
+    /// Region whose site is the closest to `direction`, which doesn't need to be normalized.
+    pub fn region_at(&self, direction: Vec3) -> Option<RegionIndex> {
+        (0..self.regions.len()).max_by(|&a, &b| {
+            self.regions[a]
+                .site
+                .dot(&direction)
+                .total_cmp(&self.regions[b].site.dot(&direction))
+        })
+    }
+
+    /// Outline of every region once projected, `None` for the regions the projection hides.
+    pub fn project(&self, projection: Projection) -> Vec<Option<Vec<Vec2>>> {
+        self.regions
+            .iter()
+            .map(|region| projection.project_region(region))
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Projection {
+    /// Longitude on x in `[-PI, PI]` and latitude on y in `[-PI / 2, PI / 2]`.
+    Equirectangular,
+    /// The hemisphere facing `center`, seen from infinitely far away, in `[-1, 1]`.
+    Orthographic { center: Vec3, up: Vec3 },
+}
+
+impl Projection {
+    /// `None` when the point is on the hidden side.
+    pub fn project(&self, point: Vec3) -> Option<Vec2> {
+        match self {
+            Projection::Equirectangular => Some(longitude_latitude(point)),
+            Projection::Orthographic { center, up } => {
+                let (right, up, forward) = frame(*center, *up);
+                (point.dot(&forward) >= 0.).then(|| Vec2::new(point.dot(&right), point.dot(&up)))
+            }
+        }
+    }
+
+    /// Equirectangular outlines are unwrapped around the site, so the regions on the
+    /// antimeridian reach past `PI`, and the regions holding a pole are closed along it.
+    fn project_region(&self, region: &PlanetRegion) -> Option<Vec<Vec2>> {
+        match self {
+            Projection::Equirectangular => {
+                let site = longitude_latitude(region.site);
+                let mut outline: Vec<Vec2> = region
+                    .corners
+                    .iter()
+                    .map(|&corner| {
+                        let mut point = longitude_latitude(corner);
+                        point.x = site.x + wrap_angle(point.x - site.x);
+                        point
+                    })
+                    .collect();
+                let mut winding = 0.;
+                for k in 0..outline.len() {
+                    let next = outline[(k + 1) % outline.len()];
+                    winding += wrap_angle(next.x - outline[k].x);
+                }
+                if winding.abs() > PI {
+                    close_around_pole(&mut outline, region.site.z.signum());
+                }
+                Some(outline)
+            }
+            Projection::Orthographic { .. } => {
+                self.project(region.site)?;
+                region
+                    .corners
+                    .iter()
+                    .map(|&corner| self.project(corner))
+                    .collect()
+            }
+        }
+    }
+}
+
+pub fn generate_planet(settings: PlanetSettings) -> Planet {
+    let mut rng = StdRng::seed_from_u64(settings.seed);
+    let count = settings.site_count.max(4);
+    let sites = match settings.distribution {
+        SiteDistribution::Fibonacci => fibonacci_sites(count, settings.jitter, &mut rng),
+        SiteDistribution::RelaxedRandom => {
+            let sites = random_sites(count, &mut rng);
+            let (corners, _) = spherical_voronoi(&sites);
+            corners
+                .iter()
+                .map(|corners| corners.iter().sum::<Vec3>().normalize())
+                .collect()
+        }
+    };
+    let (corners, neighbors) = spherical_voronoi(&sites);
+
+    let noise = terrain_noise(settings.seed);
+    let regions = map_indices(sites.len(), |i| {
+        let n = sample_noise(&noise, sites[i] * settings.noise_scale);
+        let elevation = (1. + n) / 2.;
+        let moisture = (1. - n) / 2.;
+        PlanetRegion {
+            site: sites[i],
+            corners: corners[i].clone(),
+            neighbors: neighbors[i].clone(),
+            elevation,
+            moisture,
+            color: get_biome_color(elevation, moisture).into(),
+        }
+    });
+    Planet { settings, regions }
+}
+
+fn sample_noise(noise: &FastNoise, point: Vec3) -> f32 {
+    noise.get_noise3d(point.x, point.y, point.z)
+}
+
+fn fibonacci_sites(count: usize, jitter: f32, rng: &mut StdRng) -> Vec<Vec3> {
+    let golden_angle = PI * (3. - 5f32.sqrt());
+    let spacing = (4. * PI / count as f32).sqrt();
+    (0..count)
+        .map(|i| {
+            let z = 1. - 2. * (i as f32 + 0.5) / count as f32;
+            let radius = (1. - z * z).sqrt();
+            let theta = golden_angle * i as f32;
+            let site = Vec3::new(radius * theta.cos(), radius * theta.sin(), z);
+            let nudge = Vec3::new(
+                rng.gen::<f32>() - 0.5,
+                rng.gen::<f32>() - 0.5,
+                rng.gen::<f32>() - 0.5,
+            );
+            (site + nudge * jitter * spacing).normalize()
+        })
+        .collect()
+}
+
+fn random_sites(count: usize, rng: &mut StdRng) -> Vec<Vec3> {
+    (0..count)
+        .map(|_| {
+            let z = rng.gen_range(-1f32..1.);
+            let theta = rng.gen_range(0.0..TAU);
+            let radius = (1. - z * z).sqrt();
+            Vec3::new(radius * theta.cos(), radius * theta.sin(), z)
+        })
+        .collect()
+}
+
+/// Voronoi corners and neighbors of every site, both in the order of `PlanetRegion`.
+///
+/// The convex hull of points on a sphere is their spherical Delaunay triangulation. It is built
+/// by projecting every site but the last one stereographically from the last one: the plane
+/// triangulation of the projection gives the triangles away from that site, and its convex hull
+/// gives the fan of triangles around it.
+fn spherical_voronoi(sites: &[Vec3]) -> (Vec<Vec<Vec3>>, Vec<Vec<RegionIndex>>) {
+    let pole = sites.len() - 1;
+    let (right, up, forward) = frame(sites[pole], any_perpendicular(sites[pole]));
+    let mut plane = DelaunayTriangulation::<Point2<f64>>::new();
+    for site in &sites[..pole] {
+        let depth = 1. - site.dot(&forward) as f64;
+        plane
+            .insert(Point2::new(
+                site.dot(&right) as f64 / depth,
+                site.dot(&up) as f64 / depth,
+            ))
+            .unwrap();
+    }
+
+    let mut triangles: Vec<[usize; 3]> = plane
+        .inner_faces()
+        .map(|face| face.vertices().map(|vertex| vertex.fix().index()))
+        .collect();
+    triangles.extend(
+        plane
+            .convex_hull()
+            .map(|edge| [edge.from().fix().index(), edge.to().fix().index(), pole]),
+    );
+
+    let mut incident = vec![vec![]; sites.len()];
+    for (t, triangle) in triangles.iter().enumerate() {
+        for &vertex in triangle {
+            incident[vertex].push(t);
+        }
+    }
+    let centers: Vec<Vec3> = triangles
+        .iter()
+        .map(|&[a, b, c]| {
+            let center = (sites[b] - sites[a])
+                .cross(&(sites[c] - sites[a]))
+                .normalize();
+            if center.dot(&sites[a]) < 0. {
+                -center
+            } else {
+                center
+            }
+        })
+        .collect();
+
+    let mut all_corners = Vec::with_capacity(sites.len());
+    let mut all_neighbors = Vec::with_capacity(sites.len());
+    for (site, incident) in incident.iter_mut().enumerate() {
+        let (right, up, _) = frame(sites[site], any_perpendicular(sites[site]));
+        let angle = |point: Vec3| point.dot(&up).atan2(point.dot(&right));
+        incident.sort_by(|&a, &b| angle(centers[a]).total_cmp(&angle(centers[b])));
+
+        let corners = incident.iter().map(|&t| centers[t]).collect();
+        let neighbors = (0..incident.len())
+            .map(|k| {
+                let here = &triangles[incident[k]];
+                let next = &triangles[incident[(k + 1) % incident.len()]];
+                *here
+                    .iter()
+                    .find(|&&vertex| vertex != site && next.contains(&vertex))
+                    .unwrap()
+            })
+            .collect();
+        all_corners.push(corners);
+        all_neighbors.push(neighbors);
+    }
+    (all_corners, all_neighbors)
+}
+
+/// Right handed frame looking at `forward` from outside the sphere.
+fn frame(forward: Vec3, up: Vec3) -> (Vec3, Vec3, Vec3) {
+    let forward = forward.normalize();
+    let right = up.cross(&forward).normalize();
+    let up = forward.cross(&right);
+    (right, up, forward)
+}
+
+fn any_perpendicular(direction: Vec3) -> Vec3 {
+    let axis = if direction.z.abs() < 0.9 {
+        Vec3::z()
+    } else {
+        Vec3::x()
+    };
+    axis.cross(&direction)
+}
+
+fn longitude_latitude(point: Vec3) -> Vec2 {
+    let point = point.normalize();
+    Vec2::new(point.y.atan2(point.x), point.z.clamp(-1., 1.).asin())
+}
+
+fn wrap_angle(angle: f32) -> f32 {
+    (angle + PI).rem_euclid(TAU) - PI
+}
+
+/// Cuts the outline where it crosses the antimeridian and runs it along the pole instead.
+fn close_around_pole(outline: &mut Vec<Vec2>, pole: f32) {
+    let mut longitudes: Vec<Vec2> = outline
+        .iter()
+        .map(|point| Vec2::new(wrap_angle(point.x), point.y))
+        .collect();
+    let start = (0..longitudes.len())
+        .find(|&k| longitudes[(k + 1) % longitudes.len()].x < longitudes[k].x - PI)
+        .or_else(|| {
+            (0..longitudes.len())
+                .find(|&k| longitudes[(k + 1) % longitudes.len()].x > longitudes[k].x + PI)
+        })
+        .map_or(0, |k| k + 1);
+    let len = longitudes.len();
+    longitudes.rotate_left(start % len);
+    let latitude = pole * PI / 2.;
+    let first = longitudes[0];
+    let last = longitudes[longitudes.len() - 1];
+    longitudes.push(Vec2::new(last.x, latitude));
+    longitudes.push(Vec2::new(first.x, latitude));
+    *outline = longitudes;
+}
+
+#[cfg(test)]
+mod planet_tests {
+    use super::*;
+
+    fn planet(distribution: SiteDistribution) -> Planet {
+        generate_planet(PlanetSettings {
+            site_count: 1000,
+            distribution,
+            ..Default::default()
+        })
+    }
+
+    fn area(region: &PlanetRegion) -> f32 {
+        (0..region.corners.len())
+            .map(|k| {
+                let (a, b) = region.edge(k);
+                (a - region.site).cross(&(b - region.site)).norm() / 2.
+            })
+            .sum()
+    }
+
+    #[test]
+    fn test_regions_cover_the_sphere() {
+        for distribution in [SiteDistribution::Fibonacci, SiteDistribution::RelaxedRandom] {
+            let planet = planet(distribution);
+            let regions = planet.get_regions();
+            assert_eq!(regions.len(), 1000);
+            let edges: usize = regions.iter().map(|region| region.neighbors.len()).sum();
+            // euler: a triangulation of the sphere has 3n - 6 edges
+            assert_eq!(edges, 2 * (3 * 1000 - 6));
+            let total: f32 = regions.iter().map(area).sum();
+            assert!((total - 4. * PI).abs() < 0.05 * 4. * PI, "{}", total);
+        }
+    }
+
+    #[test]
+    fn test_neighbors_share_edges() {
+        let planet = planet(SiteDistribution::Fibonacci);
+        let regions = planet.get_regions();
+        for (i, region) in regions.iter().enumerate() {
+            assert_eq!(region.corners.len(), region.neighbors.len());
+            for (k, &neighbor) in region.neighbors.iter().enumerate() {
+                let other = &regions[neighbor];
+                let back = other.neighbors.iter().position(|&n| n == i).unwrap();
+                let (a, b) = region.edge(k);
+                let (c, d) = other.edge(back);
+                assert!((a - d).norm() < 1e-4 && (b - c).norm() < 1e-4);
+            }
+        }
+    }
+
+    #[test]
+    fn test_region_at_site() {
+        let planet = planet(SiteDistribution::Fibonacci);
+        for (i, region) in planet.get_regions().iter().enumerate().step_by(37) {
+            assert_eq!(planet.region_at(region.site * 3.), Some(i));
+        }
+        assert!(planet.get_regions().iter().any(|region| region.is_water()));
+        assert!(planet.get_regions().iter().any(|region| !region.is_water()));
+    }
+
+    #[test]
+    fn test_projections() {
+        let planet = planet(SiteDistribution::Fibonacci);
+        let flat = planet.project(Projection::Equirectangular);
+        assert!(flat.iter().all(|outline| outline.is_some()));
+        let polar: Vec<&Vec<Vec2>> = flat
+            .iter()
+            .flatten()
+            .filter(|outline| outline.iter().any(|point| point.y.abs() == PI / 2.))
+            .collect();
+        assert_eq!(polar.len(), 2);
+
+        let front = Projection::Orthographic {
+            center: Vec3::x(),
+            up: Vec3::z(),
+        };
+        assert_eq!(front.project(Vec3::x()), Some(Vec2::zeros()));
+        assert_eq!(front.project(-Vec3::x()), None);
+        let visible = planet.project(front).iter().flatten().count();
+        assert!(visible > 400 && visible < 600);
+    }
+}
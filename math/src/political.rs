@@ -0,0 +1,261 @@
+use std::collections::BinaryHeap;
+use std::fmt;
+
+use nalgebra_glm::Vec2;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::graph::Candidate;
+use crate::map::{Map, MapRegion, RegionIndex};
+
+pub type OwnerId = usize;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Capital {
+    pub region: RegionIndex,
+    /// Divides every expansion cost, a kingdom with strength 2 grows twice as far.
+    pub strength: f32,
+}
+
+impl Capital {
+    pub fn new(region: RegionIndex) -> Self {
+        Self {
+            region,
+            strength: 1.0,
+        }
+    }
+}
+
+/// Price of stepping from one region into a neighbor.
+#[derive(Debug, Clone)]
+pub struct ExpansionCosts {
+    /// Per unit of distance between the two sites.
+    pub land: f32,
+    /// Per unit of elevation difference.
+    pub climb: f32,
+    pub mountain: f32,
+    pub water: f32,
+}
+
+impl Default for ExpansionCosts {
+    fn default() -> Self {
+        Self {
+            land: 1.0,
+            climb: 20.0,
+            mountain: 8.0,
+            water: 25.0,
+        }
+    }
+}
+
+impl ExpansionCosts {
+    /// `map` is only there to measure the distance across wrapping edges.
+    pub fn step(&self, map: &Map, from: &MapRegion, to: &MapRegion) -> f32 {
+        let mut cost = self.land * map.wrapped_delta(from.site, to.site).norm();
+        cost += self.climb * (to.elevation - from.elevation).abs();
+        if to.biome().is_mountain() {
+            cost += self.mountain;
+        }
+        if to.is_water() {
+            cost += self.water;
+        }
+        cost
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BorderEdge {
+    pub regions: (RegionIndex, RegionIndex),
+    pub owners: (OwnerId, OwnerId),
+    pub segment: (Vec2, Vec2),
+}
+
+/// Why `expand_kingdoms` can't start from its capitals.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KingdomError {
+    /// The capital's region is not on the map.
+    OutOfRange(RegionIndex),
+    /// Water regions have no owner, so a capital can't stand on one.
+    OnWater(RegionIndex),
+}
+
+impl fmt::Display for KingdomError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KingdomError::OutOfRange(region) => {
+                write!(f, "capital region {} is not on the map", region)
+            }
+            KingdomError::OnWater(region) => write!(f, "capital region {} is water", region),
+        }
+    }
+}
+
+impl std::error::Error for KingdomError {}
+
+pub struct Kingdoms {
+    /// Indexed by region, water regions have no owner.
+    pub owners: Vec<Option<OwnerId>>,
+    pub borders: Vec<BorderEdge>,
+}
+
+/// Spreads `count` capitals over the land with farthest point sampling.
+pub fn pick_capitals(map: &Map, count: usize, seed: u64) -> Vec<Capital> {
+    let regions = map.get_regions();
+    let land: Vec<RegionIndex> = (0..regions.len())
+        .filter(|&i| !regions[i].is_water() && map.get_boundary().point_inside(regions[i].site))
+        .collect();
+    if land.is_empty() || count == 0 {
+        return vec![];
+    }
+
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut capitals = vec![land[rng.gen_range(0..land.len())]];
+    let mut closest: Vec<f32> = land
+        .iter()
+        .map(|&i| map.site_delta(i, capitals[0]).norm_squared())
+        .collect();
+    while capitals.len() < count.min(land.len()) {
+        let (farthest, _) = closest
+            .iter()
+            .enumerate()
+            .max_by(|a, b| a.1.total_cmp(b.1))
+            .unwrap();
+        let capital = land[farthest];
+        for (d, &i) in closest.iter_mut().zip(&land) {
+            *d = d.min(map.site_delta(i, capital).norm_squared());
+        }
+        capitals.push(capital);
+    }
+    capitals.into_iter().map(Capital::new).collect()
+}
+
+/// Priority flood from every capital at once, each region goes to the cheapest kingdom to reach it.
+pub fn expand_kingdoms(
+    map: &Map,
+    capitals: &[Capital],
+    costs: &ExpansionCosts,
+) -> Result<Kingdoms, KingdomError> {
+    let regions = map.get_regions();
+    for capital in capitals {
+        match regions.get(capital.region) {
+            None => return Err(KingdomError::OutOfRange(capital.region)),
+            Some(region) if region.is_water() => return Err(KingdomError::OnWater(capital.region)),
+            Some(_) => {}
+        }
+    }
+    let mut best = vec![f32::INFINITY; regions.len()];
+    let mut owners = vec![None; regions.len()];
+    let mut frontier = BinaryHeap::new();
+    for (owner, capital) in capitals.iter().enumerate() {
+        frontier.push(Candidate::new(0.0, capital.region, owner));
+    }
+
+    while let Some(Candidate {
+        cost,
+        region,
+        origin: owner,
+    }) = frontier.pop()
+    {
+        if owners[region].is_some() {
+            continue;
+        }
+        best[region] = cost;
+        owners[region] = Some(owner);
+        let strength = capitals[owner].strength.max(f32::EPSILON);
+        for &neighbor in &regions[region].neighbors {
+            if owners[neighbor].is_some() {
+                continue;
+            }
+            let cost = cost + costs.step(map, &regions[region], &regions[neighbor]) / strength;
+            if cost < best[neighbor] {
+                best[neighbor] = cost;
+                frontier.push(Candidate::new(cost, neighbor, owner));
+            }
+        }
+    }
+
+    for (owner, region) in owners.iter_mut().zip(regions) {
+        if region.is_water() {
+            *owner = None;
+        }
+    }
+    let borders = find_borders(map, &owners);
+    Ok(Kingdoms { owners, borders })
+}
+
+fn find_borders(map: &Map, owners: &[Option<OwnerId>]) -> Vec<BorderEdge> {
+    let mut borders = vec![];
+    for (a, region) in map.get_regions().iter().enumerate() {
+        for (k, &b) in region.neighbors.iter().enumerate() {
+            if b < a {
+                continue;
+            }
+            if let (Some(owner_a), Some(owner_b)) = (owners[a], owners[b]) {
+                if owner_a != owner_b {
+                    borders.push(BorderEdge {
+                        regions: (a, b),
+                        owners: (owner_a, owner_b),
+                        segment: region.edge(k),
+                    });
+                }
+            }
+        }
+    }
+    borders
+}
+
+#[cfg(test)]
+mod political_tests {
+    use super::*;
+    use crate::map::{test_map, Wrap};
+
+    #[test]
+    fn test_every_land_region_has_an_owner() {
+        let map = test_map(Wrap::None);
+        let capitals = pick_capitals(&map, 4, 7);
+        assert_eq!(capitals.len(), 4);
+        let kingdoms = expand_kingdoms(&map, &capitals, &ExpansionCosts::default()).unwrap();
+        for (owner, region) in kingdoms.owners.iter().zip(map.get_regions()) {
+            assert_eq!(owner.is_some(), !region.is_water());
+        }
+        for (id, capital) in capitals.iter().enumerate() {
+            assert_eq!(kingdoms.owners[capital.region], Some(id));
+        }
+    }
+
+    #[test]
+    fn test_borders_separate_owners() {
+        let map = test_map(Wrap::None);
+        let capitals = pick_capitals(&map, 3, 1);
+        let kingdoms = expand_kingdoms(&map, &capitals, &ExpansionCosts::default()).unwrap();
+        assert!(!kingdoms.borders.is_empty());
+        for border in &kingdoms.borders {
+            let (a, b) = border.regions;
+            assert_ne!(border.owners.0, border.owners.1);
+            assert_eq!(kingdoms.owners[a], Some(border.owners.0));
+            assert_eq!(kingdoms.owners[b], Some(border.owners.1));
+            assert!(map.get_regions()[b].neighbors.contains(&a));
+        }
+    }
+
+    #[test]
+    fn test_rejects_bad_capitals() {
+        let map = test_map(Wrap::None);
+        let regions = map.get_regions();
+        let costs = ExpansionCosts::default();
+        let outside = Capital::new(regions.len());
+        assert_eq!(
+            expand_kingdoms(&map, &[outside], &costs).err(),
+            Some(KingdomError::OutOfRange(regions.len()))
+        );
+        let water = (0..regions.len()).find(|&i| regions[i].is_water()).unwrap();
+        let capitals = [
+            Capital::new(map.region_at(Vec2::zeros()).unwrap()),
+            Capital::new(water),
+        ];
+        assert_eq!(
+            expand_kingdoms(&map, &capitals, &costs).err(),
+            Some(KingdomError::OnWater(water))
+        );
+    }
+}
@@ -0,0 +1,289 @@
+//! Progress reports and cancellation of a map generation.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::error::MapError;
+use crate::parallel::{map_indices, map_vec};
+
+/// How many times a stage checks for cancellation and reports its progress.
+const STEPS: usize = 32;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Stage {
+    /// Placing and triangulating the sites.
+    Sites,
+    Relaxation,
+    /// Triangulating the relaxed sites.
+    Triangulation,
+    /// Extracting the Voronoi cells.
+    Regions,
+    Elevation,
+    Moisture,
+    Biomes,
+}
+
+impl Stage {
+    pub const ALL: [Stage; 7] = [
+        Stage::Sites,
+        Stage::Relaxation,
+        Stage::Triangulation,
+        Stage::Regions,
+        Stage::Elevation,
+        Stage::Moisture,
+        Stage::Biomes,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Stage::Sites => "sites",
+            Stage::Relaxation => "relaxation",
+            Stage::Triangulation => "triangulation",
+            Stage::Regions => "regions",
+            Stage::Elevation => "elevation",
+            Stage::Moisture => "moisture",
+            Stage::Biomes => "biomes",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Progress {
+    pub stage: Stage,
+    /// Done share of the current stage, 1 once it's over.
+    pub stage_fraction: f32,
+    /// Done share of the whole generation, stages count the same.
+    pub fraction: f32,
+    /// Time spent in the current stage, its whole duration once it's over.
+    pub stage_elapsed: Duration,
+}
+
+/// Told about the progress of a generation from the thread running it.
+pub trait ProgressObserver {
+    fn progress(&self, progress: &Progress);
+}
+
+impl<F: Fn(&Progress)> ProgressObserver for F {
+    fn progress(&self, progress: &Progress) {
+        self(progress)
+    }
+}
+
+/// Shared flag stopping the generations it's given to, from any thread.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Follows a generation through the stages of `plan`, reporting to the observer and checking
+/// the token between steps.
+pub(crate) struct Tracker<'a> {
+    observer: Option<&'a dyn ProgressObserver>,
+    token: Option<&'a CancellationToken>,
+    plan: &'a [Stage],
+    stage: Stage,
+    start: Instant,
+}
+
+impl<'a> Tracker<'a> {
+    pub(crate) fn new(
+        plan: &'a [Stage],
+        observer: &'a dyn ProgressObserver,
+        token: &'a CancellationToken,
+    ) -> Self {
+        Self {
+            observer: Some(observer),
+            token: Some(token),
+            plan,
+            stage: plan[0],
+            start: Instant::now(),
+        }
+    }
+
+    /// Neither reports nor gets cancelled.
+    pub(crate) fn silent() -> Self {
+        Self {
+            observer: None,
+            token: None,
+            plan: &[],
+            stage: Stage::Sites,
+            start: Instant::now(),
+        }
+    }
+
+    fn tracked(&self) -> bool {
+        self.observer.is_some() || self.token.is_some()
+    }
+
+    pub(crate) fn start(&mut self, stage: Stage) -> Result<(), MapError> {
+        self.stage = stage;
+        self.start = Instant::now();
+        self.report(0.)
+    }
+
+    pub(crate) fn finish(&self) -> Result<(), MapError> {
+        self.report(1.)
+    }
+
+    pub(crate) fn report(&self, stage_fraction: f32) -> Result<(), MapError> {
+        if self.token.is_some_and(|token| token.is_cancelled()) {
+            return Err(MapError::Cancelled);
+        }
+        if let Some(observer) = self.observer {
+            let done = self
+                .plan
+                .iter()
+                .position(|&stage| stage == self.stage)
+                .unwrap_or(0);
+            observer.progress(&Progress {
+                stage: self.stage,
+                stage_fraction,
+                fraction: (done as f32 + stage_fraction) / self.plan.len().max(1) as f32,
+                stage_elapsed: self.start.elapsed(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Called before item `i` out of `count` of a sequential loop.
+    // `usize::is_multiple_of` needs Rust 1.87
+    #[allow(clippy::manual_is_multiple_of)]
+    pub(crate) fn step(&self, i: usize, count: usize) -> Result<(), MapError> {
+        let size = count.div_ceil(STEPS).max(1);
+        if self.tracked() && i > 0 && i % size == 0 {
+            self.report(i as f32 / count as f32)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// `parallel::map_indices` run in steps.
+    pub(crate) fn map_indices<T, F>(&self, count: usize, f: F) -> Result<Vec<T>, MapError>
+    where
+        T: Send,
+        F: Fn(usize) -> T + Sync + Send,
+    {
+        if !self.tracked() {
+            return Ok(map_indices(count, f));
+        }
+        let size = count.div_ceil(STEPS).max(1);
+        let mut result = Vec::with_capacity(count);
+        for start in (0..count).step_by(size) {
+            self.report(start as f32 / count as f32)?;
+            let end = (start + size).min(count);
+            result.extend(map_indices(end - start, |i| f(start + i)));
+        }
+        Ok(result)
+    }
+
+    /// `parallel::map_vec` run in steps.
+    pub(crate) fn map_vec<T, U, F>(&self, items: Vec<T>, f: F) -> Result<Vec<U>, MapError>
+    where
+        T: Send,
+        U: Send,
+        F: Fn(T) -> U + Sync + Send,
+    {
+        if !self.tracked() {
+            return Ok(map_vec(items, f));
+        }
+        let count = items.len();
+        let size = count.div_ceil(STEPS).max(1);
+        let mut items = items.into_iter();
+        let mut result = Vec::with_capacity(count);
+        while result.len() < count {
+            self.report(result.len() as f32 / count as f32)?;
+            let chunk: Vec<T> = items.by_ref().take(size).collect();
+            result.extend(map_vec(chunk, &f));
+        }
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod progress_tests {
+    use super::*;
+    use crate::map::{
+        generate_map, generate_map_with_progress, DistanceFn, MapSettings, ReshapingFn, Wrap,
+    };
+    use crate::Boundary;
+    use nalgebra_glm::Vec2;
+    use std::sync::Mutex;
+
+    fn settings() -> MapSettings {
+        let boundary = Boundary::from_top_left(Vec2::new(-16., 16.), 32., 32.);
+        MapSettings::new(boundary, 5, DistanceFn::Euclidean, ReshapingFn::Linear)
+            .with_site_count(800)
+    }
+
+    #[test]
+    fn test_reports_every_stage() {
+        for wrap in [Wrap::None, Wrap::Both] {
+            let reports = Mutex::new(vec![]);
+            let observer = |progress: &Progress| reports.lock().unwrap().push(*progress);
+            let settings = settings().with_wrap(wrap);
+            let map =
+                generate_map_with_progress(settings.clone(), &observer, &CancellationToken::new())
+                    .unwrap();
+            assert_eq!(
+                map.content_hash(),
+                generate_map(settings).unwrap().content_hash()
+            );
+
+            let reports = reports.into_inner().unwrap();
+            let mut stages: Vec<Stage> = reports.iter().map(|report| report.stage).collect();
+            stages.dedup();
+            assert_eq!(stages, Stage::ALL);
+            for pair in reports.windows(2) {
+                assert!(pair[0].fraction <= pair[1].fraction);
+            }
+            assert!(reports
+                .iter()
+                .any(|report| report.stage_fraction > 0. && report.stage_fraction < 1.));
+            let last = reports.last().unwrap();
+            assert_eq!((last.stage, last.fraction), (Stage::Biomes, 1.));
+        }
+    }
+
+    #[test]
+    fn test_cancellation() {
+        let token = CancellationToken::new();
+        let after = Mutex::new(0);
+        let observer = |progress: &Progress| {
+            if token.is_cancelled() {
+                *after.lock().unwrap() += 1;
+            } else if progress.stage == Stage::Relaxation && progress.stage_fraction > 0.2 {
+                token.cancel();
+            }
+        };
+        let result = generate_map_with_progress(settings(), &observer, &token);
+        assert!(matches!(result, Err(MapError::Cancelled)));
+        assert_eq!(*after.lock().unwrap(), 0);
+
+        // regenerating the terrain only goes through its own stages
+        let map = generate_map(settings()).unwrap();
+        let stages = Mutex::new(vec![]);
+        let observer = |progress: &Progress| stages.lock().unwrap().push(progress.stage);
+        let settings = MapSettings {
+            seed: 6,
+            ..settings()
+        };
+        map.regenerate_with_progress(settings, &observer, &CancellationToken::new())
+            .unwrap();
+        let mut stages = stages.into_inner().unwrap();
+        stages.dedup();
+        assert_eq!(stages, [Stage::Elevation, Stage::Moisture, Stage::Biomes]);
+    }
+}
@@ -0,0 +1,507 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use nalgebra_glm::Vec2;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::map::{DistanceFn, Map, MapRegion, MapSettings, RegionIndex, ReshapingFn, Wrap};
+use crate::voronoi::VoronoiVertex;
+use crate::Boundary;
+
+/// Bumped whenever `MapFile` changes. The previous layouts stay around as `MapFileV<n>` with a
+/// conversion to the current one, see `parse_json` and `parse_binary`.
+pub const FORMAT_VERSION: u32 = 2;
+const MAGIC: &[u8; 4] = b"VMAP";
+
+#[derive(Debug)]
+pub enum FileError {
+    Io(io::Error),
+    Json(serde_json::Error),
+    Binary(bincode::Error),
+    /// Not a map file, or a JSON document without a version that fits a `u32`.
+    BadHeader,
+    UnsupportedVersion(u32),
+    /// The sites can't be triangulated again, e.g. a coordinate is not finite or two sites
+    /// coincide.
+    BadSites,
+    /// A region or ghost refers to a region that doesn't exist, or its polygon doesn't match
+    /// its neighbors.
+    BadRegion(RegionIndex),
+}
+
+impl fmt::Display for FileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FileError::Io(error) => write!(f, "{}", error),
+            FileError::Json(error) => write!(f, "invalid json map: {}", error),
+            FileError::Binary(error) => write!(f, "invalid binary map: {}", error),
+            FileError::BadHeader => write!(f, "not a map file"),
+            FileError::UnsupportedVersion(version) => write!(
+                f,
+                "map format version {} is newer than the supported version {}",
+                version, FORMAT_VERSION
+            ),
+            FileError::BadSites => write!(f, "the map sites can't be triangulated"),
+            FileError::BadRegion(region) => write!(f, "region {} is corrupt", region),
+        }
+    }
+}
+
+impl std::error::Error for FileError {}
+
+impl From<io::Error> for FileError {
+    fn from(error: io::Error) -> Self {
+        FileError::Io(error)
+    }
+}
+
+impl From<serde_json::Error> for FileError {
+    fn from(error: serde_json::Error) -> Self {
+        FileError::Json(error)
+    }
+}
+
+impl From<bincode::Error> for FileError {
+    fn from(error: bincode::Error) -> Self {
+        FileError::Binary(error)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Format {
+    Json,
+    Binary,
+}
+
+impl Format {
+    /// `.json` files are JSON, anything else is binary.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some(extension) if extension.eq_ignore_ascii_case("json") => Format::Json,
+            _ => Format::Binary,
+        }
+    }
+}
+
+/// Everything needed to restore a `Map` without generating it again.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MapFile {
+    pub version: u32,
+    pub settings: SettingsRecord,
+    pub regions: Vec<RegionRecord>,
+    /// Copies of the regions of a wrapping map, see `Wrap`.
+    pub ghosts: Vec<GhostRecord>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SettingsRecord {
+    /// Top left corner of the boundary.
+    pub origin: [f32; 2],
+    pub width: f32,
+    pub height: f32,
+    pub site_seed: u64,
+    pub seed: u64,
+    pub distance_fn: DistanceFn,
+    pub reshape_fn: ReshapingFn,
+    pub wrap: Wrap,
+    pub site_count: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RegionRecord {
+    pub site: [f32; 2],
+    /// Same layout as `MapRegion::vertices`.
+    pub polygon: Vec<[f32; 2]>,
+    pub neighbors: Vec<RegionIndex>,
+    pub elevation: f32,
+    pub moisture: f32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GhostRecord {
+    pub site: [f32; 2],
+    pub region: RegionIndex,
+}
+
+impl MapFile {
+    pub fn from_map(map: &Map) -> Self {
+        let settings = map.get_settings();
+        let boundary = &settings.boundary;
+        MapFile {
+            version: FORMAT_VERSION,
+            settings: SettingsRecord {
+                origin: boundary.top_left().into(),
+                width: boundary.width(),
+                height: boundary.height(),
+                site_seed: settings.site_seed,
+                seed: settings.seed,
+                distance_fn: settings.distance_fn,
+                reshape_fn: settings.reshape_fn,
+                wrap: settings.wrap,
+                site_count: settings.site_count,
+            },
+            regions: map
+                .get_regions()
+                .iter()
+                .map(|region| RegionRecord {
+                    site: region.site.into(),
+                    polygon: region
+                        .vertices
+                        .iter()
+                        .map(|vertex| vertex.position().into())
+                        .collect(),
+                    neighbors: region.neighbors.clone(),
+                    elevation: region.elevation,
+                    moisture: region.moisture,
+                })
+                .collect(),
+            ghosts: map
+                .ghost_sites()
+                .into_iter()
+                .map(|(site, region)| GhostRecord {
+                    site: site.into(),
+                    region,
+                })
+                .collect(),
+        }
+    }
+
+    /// Checks that the regions and ghosts are consistent, `Map` indexes them without checking.
+    fn check(&self) -> Result<(), FileError> {
+        let count = self.regions.len();
+        for (i, region) in self.regions.iter().enumerate() {
+            if !region.site.iter().all(|c| c.is_finite()) {
+                return Err(FileError::BadSites);
+            }
+            if region.polygon.len() != 2 * region.neighbors.len()
+                || region.neighbors.iter().any(|&neighbor| neighbor >= count)
+            {
+                return Err(FileError::BadRegion(i));
+            }
+        }
+        for (i, ghost) in self.ghosts.iter().enumerate() {
+            if !ghost.site.iter().all(|c| c.is_finite()) {
+                return Err(FileError::BadSites);
+            }
+            if ghost.region >= count {
+                return Err(FileError::BadRegion(count + i));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn into_map(self) -> Result<Map, FileError> {
+        self.check()?;
+        let vertex_count = self.regions.len() + self.ghosts.len();
+        let record = self.settings;
+        let settings = MapSettings {
+            boundary: Boundary::from_top_left(record.origin.into(), record.width, record.height),
+            site_seed: record.site_seed,
+            seed: record.seed,
+            distance_fn: record.distance_fn,
+            reshape_fn: record.reshape_fn,
+            wrap: record.wrap,
+            site_count: record.site_count,
+        };
+        let regions = self
+            .regions
+            .into_iter()
+            .map(|region| {
+                MapRegion::new(
+                    region.site.into(),
+                    region
+                        .polygon
+                        .into_iter()
+                        .map(|point| VoronoiVertex::Inner(Vec2::from(point)))
+                        .collect(),
+                    region.neighbors,
+                    region.elevation,
+                    region.moisture,
+                )
+            })
+            .collect();
+        let ghosts = self
+            .ghosts
+            .into_iter()
+            .map(|ghost| (ghost.site.into(), ghost.region))
+            .collect();
+        let map = Map::from_regions(settings, regions, ghosts).map_err(|_| FileError::BadSites)?;
+        // spade merges sites at the same position, which would shift the ghost vertices
+        if map.vertex_count() != vertex_count {
+            return Err(FileError::BadSites);
+        }
+        Ok(map)
+    }
+}
+
+/// Version 1, before `SettingsRecord::site_count`.
+#[derive(Deserialize)]
+struct MapFileV1 {
+    // read by bincode, which has no field names to skip it by
+    #[serde(rename = "version")]
+    _version: u32,
+    settings: SettingsRecordV1,
+    regions: Vec<RegionRecord>,
+    ghosts: Vec<GhostRecord>,
+}
+
+#[derive(Deserialize)]
+struct SettingsRecordV1 {
+    origin: [f32; 2],
+    width: f32,
+    height: f32,
+    site_seed: u64,
+    seed: u64,
+    distance_fn: DistanceFn,
+    reshape_fn: ReshapingFn,
+    wrap: Wrap,
+}
+
+impl From<MapFileV1> for MapFile {
+    fn from(file: MapFileV1) -> Self {
+        let settings = file.settings;
+        MapFile {
+            version: FORMAT_VERSION,
+            settings: SettingsRecord {
+                origin: settings.origin,
+                width: settings.width,
+                height: settings.height,
+                site_seed: settings.site_seed,
+                seed: settings.seed,
+                distance_fn: settings.distance_fn,
+                reshape_fn: settings.reshape_fn,
+                wrap: settings.wrap,
+                site_count: None,
+            },
+            regions: file.regions,
+            ghosts: file.ghosts,
+        }
+    }
+}
+
+pub fn write_json<W: Write>(map: &Map, writer: W) -> Result<(), FileError> {
+    serde_json::to_writer_pretty(writer, &MapFile::from_map(map))?;
+    Ok(())
+}
+
+pub fn read_json<R: Read>(reader: R) -> Result<Map, FileError> {
+    let value: Value = serde_json::from_reader(reader)?;
+    let version = value
+        .get("version")
+        .and_then(Value::as_u64)
+        .and_then(|version| u32::try_from(version).ok())
+        .ok_or(FileError::BadHeader)?;
+    parse_json(version, value)?.into_map()
+}
+
+/// A four byte magic, the version as a little endian `u32`, then the bincode encoded `MapFile`.
+pub fn write_binary<W: Write>(map: &Map, mut writer: W) -> Result<(), FileError> {
+    writer.write_all(MAGIC)?;
+    writer.write_all(&FORMAT_VERSION.to_le_bytes())?;
+    bincode::serialize_into(writer, &MapFile::from_map(map))?;
+    Ok(())
+}
+
+pub fn read_binary<R: Read>(mut reader: R) -> Result<Map, FileError> {
+    let mut header = [0; 8];
+    reader
+        .read_exact(&mut header)
+        .map_err(|_| FileError::BadHeader)?;
+    if &header[..4] != MAGIC {
+        return Err(FileError::BadHeader);
+    }
+    let version = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+    parse_binary(version, reader)?.into_map()
+}
+
+pub fn save_map(map: &Map, path: impl AsRef<Path>) -> Result<(), FileError> {
+    let path = path.as_ref();
+    let mut writer = BufWriter::new(File::create(path)?);
+    match Format::from_path(path) {
+        Format::Json => write_json(map, &mut writer)?,
+        Format::Binary => write_binary(map, &mut writer)?,
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+pub fn load_map(path: impl AsRef<Path>) -> Result<Map, FileError> {
+    let path = path.as_ref();
+    let reader = BufReader::new(File::open(path)?);
+    match Format::from_path(path) {
+        Format::Json => read_json(reader),
+        Format::Binary => read_binary(reader),
+    }
+}
+
+fn parse_json(version: u32, value: Value) -> Result<MapFile, FileError> {
+    match version {
+        1 => Ok(serde_json::from_value::<MapFileV1>(value)?.into()),
+        FORMAT_VERSION => Ok(serde_json::from_value(value)?),
+        _ => Err(FileError::UnsupportedVersion(version)),
+    }
+}
+
+fn parse_binary<R: Read>(version: u32, reader: R) -> Result<MapFile, FileError> {
+    match version {
+        1 => Ok(bincode::deserialize_from::<_, MapFileV1>(reader)?.into()),
+        FORMAT_VERSION => Ok(bincode::deserialize_from(reader)?),
+        _ => Err(FileError::UnsupportedVersion(version)),
+    }
+}
+
+#[cfg(test)]
+mod save_tests {
+    use super::*;
+    use crate::map::{test_map, Wrap};
+
+    fn assert_same_map(a: &Map, b: &Map) {
+        assert_eq!(a.get_settings(), b.get_settings());
+        assert_eq!(a.get_regions().len(), b.get_regions().len());
+        for (ra, rb) in a.get_regions().iter().zip(b.get_regions()) {
+            assert_eq!(ra.site, rb.site);
+            assert_eq!(ra.neighbors, rb.neighbors);
+            assert_eq!(ra.elevation, rb.elevation);
+            assert_eq!(ra.moisture, rb.moisture);
+            assert_eq!(ra.color, rb.color);
+            for (va, vb) in ra.vertices.iter().zip(&rb.vertices) {
+                assert_eq!(va.position(), vb.position());
+            }
+        }
+        for point in [
+            Vec2::new(3.3, -7.1),
+            Vec2::new(-31.9, 31.9),
+            Vec2::new(20., 2.),
+        ] {
+            assert_eq!(a.region_at(point), b.region_at(point));
+        }
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        let map = test_map(Wrap::None);
+        let mut bytes = vec![];
+        write_json(&map, &mut bytes).unwrap();
+        let text = String::from_utf8(bytes.clone()).unwrap();
+        assert!(text.contains("\"version\": 2"));
+        assert_same_map(&map, &read_json(bytes.as_slice()).unwrap());
+    }
+
+    #[test]
+    fn test_binary_round_trip() {
+        let map = test_map(Wrap::Both);
+        let mut bytes = vec![];
+        write_binary(&map, &mut bytes).unwrap();
+        let loaded = read_binary(bytes.as_slice()).unwrap();
+        assert_same_map(&map, &loaded);
+        assert_eq!(
+            loaded.region_at(Vec2::new(32.3, 0.)),
+            map.region_at(Vec2::new(-31.7, 0.))
+        );
+    }
+
+    #[test]
+    fn test_rejects_unknown_versions() {
+        let mut bytes = vec![];
+        write_binary(&test_map(Wrap::None), &mut bytes).unwrap();
+        bytes[4..8].copy_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+        assert!(matches!(
+            read_binary(bytes.as_slice()),
+            Err(FileError::UnsupportedVersion(3))
+        ));
+        assert!(matches!(
+            read_binary(&b"PNG\0...."[..]),
+            Err(FileError::BadHeader)
+        ));
+        assert!(matches!(
+            read_json(&b"{\"regions\": []}"[..]),
+            Err(FileError::BadHeader)
+        ));
+        // would be read as version 2 if it were truncated
+        let mut value = serde_json::to_value(MapFile::from_map(&test_map(Wrap::None))).unwrap();
+        value["version"] = ((1u64 << 32) + 2).into();
+        assert!(matches!(
+            read_json(value.to_string().as_bytes()),
+            Err(FileError::BadHeader)
+        ));
+    }
+
+    #[test]
+    fn test_reads_version_1() {
+        let map = test_map(Wrap::None);
+        let mut value = serde_json::to_value(MapFile::from_map(&map)).unwrap();
+        value["version"] = 1.into();
+        value["settings"]
+            .as_object_mut()
+            .unwrap()
+            .remove("site_count");
+        let loaded = read_json(value.to_string().as_bytes()).unwrap();
+        assert_same_map(&map, &loaded);
+    }
+
+    #[test]
+    fn test_reads_binary_version_1() {
+        let map = test_map(Wrap::Both);
+        let file = MapFile::from_map(&map);
+        let s = &file.settings;
+        // the version 1 layout, bincode writes a tuple just like a struct with the same fields
+        let settings = (
+            s.origin,
+            s.width,
+            s.height,
+            s.site_seed,
+            s.seed,
+            s.distance_fn,
+            s.reshape_fn,
+            s.wrap,
+        );
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&1u32.to_le_bytes());
+        bincode::serialize_into(&mut bytes, &(1u32, settings, &file.regions, &file.ghosts))
+            .unwrap();
+        assert_same_map(&map, &read_binary(bytes.as_slice()).unwrap());
+    }
+
+    #[test]
+    fn test_rejects_corrupted_files() {
+        let file = MapFile::from_map(&test_map(Wrap::Both));
+        let load = |corrupt: &dyn Fn(&mut MapFile)| {
+            let mut file = file.clone();
+            corrupt(&mut file);
+            file.into_map()
+        };
+        assert!(load(&|_| {}).is_ok());
+        let count = file.regions.len();
+        assert!(matches!(
+            load(&|file| file.regions[3].neighbors[0] = count),
+            Err(FileError::BadRegion(3))
+        ));
+        assert!(matches!(
+            load(&|file| file.ghosts[1].region = count + 7),
+            Err(FileError::BadRegion(i)) if i == count + 1
+        ));
+        assert!(matches!(
+            load(&|file| {
+                file.regions[5].polygon.pop();
+            }),
+            Err(FileError::BadRegion(5))
+        ));
+        assert!(matches!(
+            load(&|file| file.regions[2].site = file.regions[1].site),
+            Err(FileError::BadSites)
+        ));
+        assert!(matches!(
+            load(&|file| file.ghosts[0].site[1] = f32::INFINITY),
+            Err(FileError::BadSites)
+        ));
+    }
+
+    #[test]
+    fn test_format_from_path() {
+        assert_eq!(Format::from_path(Path::new("world.JSON")), Format::Json);
+        assert_eq!(Format::from_path(Path::new("world.map")), Format::Binary);
+    }
+}
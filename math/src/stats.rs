@@ -0,0 +1,397 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+use nalgebra_glm::Vec2;
+use serde::Serialize;
+
+use crate::map::{Biome, Map, MapRegion, RegionIndex, Wrap};
+use crate::voronoi::VoronoiCorner;
+
+const HISTOGRAM_BINS: usize = 10;
+
+/// Counts of values in equal bins over `[0, 1]`, the last bin also takes 1.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Histogram {
+    pub bins: Vec<usize>,
+}
+
+impl Histogram {
+    fn new(values: impl Iterator<Item = f32>) -> Self {
+        let mut bins = vec![0; HISTOGRAM_BINS];
+        for value in values {
+            let bin = (value.clamp(0., 1.) * HISTOGRAM_BINS as f32) as usize;
+            bins[bin.min(HISTOGRAM_BINS - 1)] += 1;
+        }
+        Self { bins }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AreaStatistics {
+    pub min: f32,
+    pub max: f32,
+    pub mean: f32,
+    pub std_dev: f32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BiomeStatistics {
+    pub regions: usize,
+    pub area: f32,
+}
+
+/// Report built by `Map::statistics`. Only the regions whose site is inside the boundary are
+/// counted, and their areas are in map units clipped to the boundary.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MapStatistics {
+    pub regions: usize,
+    /// Distinct Voronoi corners.
+    pub corners: usize,
+    pub cell_area: AreaStatistics,
+    pub land_area: f32,
+    pub water_area: f32,
+    pub land_ratio: f32,
+    /// Every biome, missing ones included, by their name.
+    pub biomes: BTreeMap<String, BiomeStatistics>,
+    pub elevation: Histogram,
+    pub moisture: Histogram,
+    pub landmasses: usize,
+    /// Bodies of water that don't reach the edge of the map.
+    pub lakes: usize,
+    /// Total length of the edges between land and water.
+    pub coastline_length: f32,
+}
+
+impl Map {
+    pub fn statistics(&self) -> MapStatistics {
+        let (min, max) = self.clip_rect();
+        // the padding around the boundary doesn't count, only the regions with a site inside
+        let regions: Vec<&MapRegion> = self
+            .get_regions()
+            .iter()
+            .filter(|region| {
+                let site = region.site;
+                (min.x..=max.x).contains(&site.x) && (min.y..=max.y).contains(&site.y)
+            })
+            .collect();
+        let areas: Vec<f32> = regions
+            .iter()
+            .map(|region| self.area_inside(region))
+            .collect();
+        let count = areas.len().max(1) as f32;
+        let mean = areas.iter().sum::<f32>() / count;
+        let variance = areas.iter().map(|area| (area - mean).powi(2)).sum::<f32>() / count;
+        let cell_area = AreaStatistics {
+            min: areas.iter().copied().fold(f32::INFINITY, f32::min),
+            max: areas.iter().copied().fold(0., f32::max),
+            mean,
+            std_dev: variance.sqrt(),
+        };
+
+        let mut biomes: BTreeMap<String, BiomeStatistics> = Biome::ALL
+            .iter()
+            .map(|biome| {
+                let empty = BiomeStatistics {
+                    regions: 0,
+                    area: 0.,
+                };
+                (format!("{:?}", biome), empty)
+            })
+            .collect();
+        let (mut land_area, mut water_area) = (0., 0.);
+        for (region, &area) in regions.iter().zip(&areas) {
+            let biome = biomes.get_mut(&format!("{:?}", region.biome())).unwrap();
+            biome.regions += 1;
+            biome.area += area;
+            if region.is_water() {
+                water_area += area;
+            } else {
+                land_area += area;
+            }
+        }
+
+        let corners: BTreeSet<VoronoiCorner> = regions
+            .iter()
+            .flat_map(|region| region.polygon())
+            .map(VoronoiCorner::from)
+            .collect();
+        let all = self.get_regions();
+        let mut coastline_length = 0.;
+        for region in all.iter().filter(|region| !region.is_water()) {
+            for (k, &neighbor) in region.neighbors.iter().enumerate() {
+                if all[neighbor].is_water() {
+                    let (a, b) = region.edge(k);
+                    if let Some((a, b)) = clip_segment(a, b, min, max) {
+                        coastline_length += (b - a).norm();
+                    }
+                }
+            }
+        }
+
+        MapStatistics {
+            regions: regions.len(),
+            corners: corners.len(),
+            cell_area,
+            land_area,
+            water_area,
+            land_ratio: land_area / (land_area + water_area).max(f32::MIN_POSITIVE),
+            biomes,
+            elevation: Histogram::new(regions.iter().map(|region| region.elevation)),
+            moisture: Histogram::new(regions.iter().map(|region| region.moisture)),
+            landmasses: self.landmasses().len(),
+            lakes: self.lakes().len(),
+            coastline_length,
+        }
+    }
+
+    /// Bodies of water none of whose regions reach the edge of the map. When the map wraps
+    /// both ways nothing reaches an edge, and the largest body is taken as the ocean instead.
+    pub fn lakes(&self) -> Vec<Vec<RegionIndex>> {
+        let mut water = self.components(MapRegion::is_water);
+        let before = water.len();
+        water.retain(|body| !body.iter().any(|&region| self.reaches_edge(region)));
+        if self.get_settings().wrap == Wrap::Both && water.len() == before && !water.is_empty() {
+            water.remove(0);
+        }
+        water
+    }
+
+    fn reaches_edge(&self, region: RegionIndex) -> bool {
+        let boundary = self.get_boundary();
+        self.get_regions()[region]
+            .polygon()
+            .into_iter()
+            .any(|corner| !boundary.point_inside(self.wrap_point(corner)))
+    }
+
+    /// Area of the part of the region inside the boundary.
+    pub(crate) fn area_inside(&self, region: &MapRegion) -> f32 {
+        let (min, max) = self.clip_rect();
+        polygon_area(&clip_polygon(region.polygon(), min, max)).abs()
+    }
+
+    /// Corners of the boundary, open along the wrapping axes where a region overhanging the
+    /// boundary stands for the part on the other side.
+    pub(crate) fn clip_rect(&self) -> (Vec2, Vec2) {
+        let boundary = self.get_boundary();
+        let wrap = self.get_settings().wrap;
+        let (mut min, mut max) = (boundary.bottom_left(), boundary.top_right());
+        if wrap.horizontal() {
+            (min.x, max.x) = (f32::NEG_INFINITY, f32::INFINITY);
+        }
+        if wrap.vertical() {
+            (min.y, max.y) = (f32::NEG_INFINITY, f32::INFINITY);
+        }
+        (min, max)
+    }
+}
+
+/// Signed, positive for counterclockwise polygons.
+pub(crate) fn polygon_area(polygon: &[Vec2]) -> f32 {
+    (0..polygon.len())
+        .map(|k| {
+            let (a, b) = (polygon[k], polygon[(k + 1) % polygon.len()]);
+            a.x * b.y - b.x * a.y
+        })
+        .sum::<f32>()
+        / 2.
+}
+
+/// Sutherland-Hodgman against the rectangle from `min` to `max`.
+pub(crate) fn clip_polygon(mut polygon: Vec<Vec2>, min: Vec2, max: Vec2) -> Vec<Vec2> {
+    let planes: [(usize, f32, bool); 4] = [
+        (0, min.x, true),
+        (0, max.x, false),
+        (1, min.y, true),
+        (1, max.y, false),
+    ];
+    for (axis, limit, keep_above) in planes {
+        if !limit.is_finite() || polygon.is_empty() {
+            continue;
+        }
+        let inside = |p: &Vec2| (p[axis] >= limit) == keep_above || p[axis] == limit;
+        let mut clipped = Vec::with_capacity(polygon.len() + 2);
+        for k in 0..polygon.len() {
+            let (a, b) = (polygon[k], polygon[(k + 1) % polygon.len()]);
+            if inside(&a) {
+                clipped.push(a);
+            }
+            if inside(&a) != inside(&b) {
+                let t = (limit - a[axis]) / (b[axis] - a[axis]);
+                clipped.push(a + (b - a) * t);
+            }
+        }
+        polygon = clipped;
+    }
+    polygon
+}
+
+/// Liang-Barsky, `None` when the segment misses the rectangle.
+pub(crate) fn clip_segment(a: Vec2, b: Vec2, min: Vec2, max: Vec2) -> Option<(Vec2, Vec2)> {
+    let (mut enter, mut exit) = (0f32, 1f32);
+    let direction = b - a;
+    for axis in 0..2 {
+        for (limit, sign) in [(min[axis], -1.), (max[axis], 1.)] {
+            if !limit.is_finite() {
+                continue;
+            }
+            // inside while sign * (a + t * direction - limit) <= 0
+            let p = sign * direction[axis];
+            let q = sign * (limit - a[axis]);
+            if p == 0. {
+                if q < 0. {
+                    return None;
+                }
+            } else if p < 0. {
+                enter = enter.max(q / p);
+            } else {
+                exit = exit.min(q / p);
+            }
+        }
+    }
+    (enter <= exit).then(|| (a + direction * enter, a + direction * exit))
+}
+
+impl fmt::Display for MapStatistics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "regions      {}", self.regions)?;
+        writeln!(f, "corners      {}", self.corners)?;
+        let area = &self.cell_area;
+        writeln!(
+            f,
+            "cell area    min {:.3}  mean {:.3}  max {:.3}  std dev {:.3}",
+            area.min, area.mean, area.max, area.std_dev
+        )?;
+        writeln!(
+            f,
+            "land         {:.1}%  ({:.1} land, {:.1} water)",
+            self.land_ratio * 100.,
+            self.land_area,
+            self.water_area
+        )?;
+        writeln!(f, "landmasses   {}", self.landmasses)?;
+        writeln!(f, "lakes        {}", self.lakes)?;
+        writeln!(f, "coastline    {:.1}", self.coastline_length)?;
+        writeln!(f, "biomes")?;
+        let total = (self.land_area + self.water_area).max(f32::MIN_POSITIVE);
+        for (name, biome) in &self.biomes {
+            writeln!(
+                f,
+                "  {:<14}{:>6} regions {:>6.1}%",
+                name,
+                biome.regions,
+                biome.area / total * 100.
+            )?;
+        }
+        for (name, histogram) in [("elevation", &self.elevation), ("moisture", &self.moisture)] {
+            writeln!(f, "{}", name)?;
+            let largest = histogram.bins.iter().copied().max().unwrap_or(0).max(1);
+            let width = 1. / histogram.bins.len() as f32;
+            for (i, &count) in histogram.bins.iter().enumerate() {
+                writeln!(
+                    f,
+                    "  {:.1}-{:.1} {:>6} {}",
+                    i as f32 * width,
+                    (i + 1) as f32 * width,
+                    count,
+                    "#".repeat(count * 40 / largest)
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod stats_tests {
+    use super::*;
+    use crate::map::{generate_map, test_map, DistanceFn, MapSettings, ReshapingFn, Wrap};
+    use crate::Boundary;
+
+    #[test]
+    fn test_clipping() {
+        let square = vec![
+            Vec2::new(-1., -1.),
+            Vec2::new(1., -1.),
+            Vec2::new(1., 1.),
+            Vec2::new(-1., 1.),
+        ];
+        assert_eq!(polygon_area(&square), 4.);
+        let clipped = clip_polygon(square.clone(), Vec2::new(0., -5.), Vec2::new(5., 0.5));
+        assert!((polygon_area(&clipped) - 1.5).abs() < 1e-6);
+        let open = Vec2::new(f32::NEG_INFINITY, f32::NEG_INFINITY);
+        assert_eq!(clip_polygon(square.clone(), open, -open), square);
+        assert!(clip_polygon(square, Vec2::new(2., 2.), Vec2::new(3., 3.)).is_empty());
+
+        let (min, max) = (Vec2::new(0., 0.), Vec2::new(4., 2.));
+        let (a, b) = clip_segment(Vec2::new(-2., 1.), Vec2::new(6., 1.), min, max).unwrap();
+        assert_eq!((a, b), (Vec2::new(0., 1.), Vec2::new(4., 1.)));
+        let inside = (Vec2::new(1., 1.), Vec2::new(2., 0.5));
+        assert_eq!(clip_segment(inside.0, inside.1, min, max), Some(inside));
+        assert_eq!(
+            clip_segment(Vec2::new(-1., 3.), Vec2::new(5., 3.), min, max),
+            None
+        );
+        assert_eq!(
+            clip_segment(Vec2::new(-1., 1.), Vec2::new(1., 5.), min, max),
+            None
+        );
+    }
+
+    #[test]
+    fn test_areas_add_up() {
+        let map = test_map(Wrap::None);
+        let stats = map.statistics();
+        let inside = map
+            .get_regions()
+            .iter()
+            .filter(|region| map.get_boundary().point_inside(region.site))
+            .count();
+        assert_eq!(stats.regions, inside);
+        assert!(stats.regions < map.get_regions().len());
+        // the padding cells reaching over the edge leave a few slivers out
+        let total = stats.land_area + stats.water_area;
+        assert!((total - 64. * 64.).abs() < 0.01 * 64. * 64.);
+        assert!((total - stats.cell_area.mean * stats.regions as f32).abs() < 1.);
+        let biome_area: f32 = stats.biomes.values().map(|biome| biome.area).sum();
+        assert!((biome_area - total).abs() < 1e-3 * total);
+        let biome_regions: usize = stats.biomes.values().map(|biome| biome.regions).sum();
+        assert_eq!(biome_regions, stats.regions);
+        assert_eq!(stats.elevation.bins.iter().sum::<usize>(), stats.regions);
+        assert!(stats.landmasses >= 1);
+        assert!(stats.coastline_length > 0.);
+        assert!(stats.coastline_length < 64. * 64.);
+        assert!(stats.cell_area.min <= stats.cell_area.mean);
+
+        let text = stats.to_string();
+        assert!(text.contains("landmasses"));
+        assert!(text.contains("GrassLand"));
+        let json = serde_json::to_value(&stats).unwrap();
+        assert_eq!(json["regions"], stats.regions);
+        assert_eq!(json["elevation"]["bins"].as_array().unwrap().len(), 10);
+    }
+
+    #[test]
+    fn test_wrapped_areas_and_lakes() {
+        let boundary = Boundary::from_top_left(Vec2::new(0., 32.), 48., 32.);
+        let settings = MapSettings::new(boundary, 4, DistanceFn::Euclidean, ReshapingFn::Linear)
+            .with_wrap(Wrap::Both);
+        let map = generate_map(settings).unwrap();
+        let stats = map.statistics();
+        assert!((stats.land_area + stats.water_area - 48. * 32.).abs() < 1.);
+        let water = map.components(MapRegion::is_water).len();
+        assert_eq!(stats.lakes, water.saturating_sub(1));
+    }
+
+    #[test]
+    fn test_lake_without_an_ocean() {
+        let map = test_map(Wrap::None);
+        let lake = map.region_at(Vec2::zeros()).unwrap();
+        let mut regions = map.get_regions().clone();
+        for (i, region) in regions.iter_mut().enumerate() {
+            region.elevation = if i == lake { 0. } else { 1. };
+        }
+        let map = Map::from_regions(map.get_settings().clone(), regions, vec![]).unwrap();
+        assert_eq!(map.lakes(), vec![vec![lake]]);
+    }
+}
@@ -0,0 +1,490 @@
+use std::fmt;
+
+use nalgebra_glm::Vec2;
+
+use crate::error::MapError;
+use crate::map::{generate_map, Map, MapSettings, RegionIndex};
+use crate::stats::polygon_area;
+
+#[derive(Debug, Clone)]
+pub struct ValidationOptions {
+    /// Relative slack for the geometric checks.
+    pub tolerance: f32,
+    /// How far past the boundary a corner may be, in cells of the average size. Generation
+    /// places two rows of sites outside the boundary so the regions along the edge reach past it.
+    pub max_overhang: f32,
+}
+
+impl Default for ValidationOptions {
+    fn default() -> Self {
+        Self {
+            tolerance: 1e-3,
+            max_overhang: 3.,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Issue {
+    /// A site, corner, elevation or moisture is NaN or infinite.
+    NotFinite {
+        region: RegionIndex,
+    },
+    /// Fewer than three corners, or no area.
+    Degenerate {
+        region: RegionIndex,
+    },
+    /// The edge shared with `neighbors[edge]` doesn't start where the previous one ends.
+    OpenPolygon {
+        region: RegionIndex,
+        edge: usize,
+    },
+    NotConvex {
+        region: RegionIndex,
+        corner: Vec2,
+    },
+    /// Wound the other way than the rest of the regions.
+    WrongWinding {
+        region: RegionIndex,
+    },
+    OutsideBoundary {
+        region: RegionIndex,
+        corner: Vec2,
+    },
+    /// Out of range or the region itself.
+    BadNeighbor {
+        region: RegionIndex,
+        neighbor: RegionIndex,
+    },
+    /// `neighbor` doesn't list `region` back.
+    OneWayNeighbor {
+        region: RegionIndex,
+        neighbor: RegionIndex,
+    },
+    /// The two neighbors don't agree on the edge between them.
+    UnsharedEdge {
+        region: RegionIndex,
+        neighbor: RegionIndex,
+    },
+    /// The two regions share `area`, more than an edge.
+    Overlap {
+        region: RegionIndex,
+        other: RegionIndex,
+        area: f32,
+    },
+    /// The regions cover `covered` of the `expected` boundary area, they leave gaps or overlap.
+    Coverage {
+        covered: f32,
+        expected: f32,
+    },
+    /// A setting out of its range, see `WorldSettings::validate`.
+    BadSetting {
+        name: &'static str,
+    },
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Issue::NotFinite { region } => write!(f, "region {} has a non finite value", region),
+            Issue::Degenerate { region } => write!(f, "region {} is degenerate", region),
+            Issue::OpenPolygon { region, edge } => {
+                write!(f, "region {} is open before edge {}", region, edge)
+            }
+            Issue::NotConvex { region, corner } => write!(
+                f,
+                "region {} is concave at ({}, {})",
+                region, corner.x, corner.y
+            ),
+            Issue::WrongWinding { region } => write!(f, "region {} is wound backwards", region),
+            Issue::OutsideBoundary { region, corner } => write!(
+                f,
+                "region {} reaches ({}, {}) outside the boundary",
+                region, corner.x, corner.y
+            ),
+            Issue::BadNeighbor { region, neighbor } => {
+                write!(f, "region {} has invalid neighbor {}", region, neighbor)
+            }
+            Issue::OneWayNeighbor { region, neighbor } => write!(
+                f,
+                "region {} lists {} as neighbor but not the other way",
+                region, neighbor
+            ),
+            Issue::UnsharedEdge { region, neighbor } => write!(
+                f,
+                "regions {} and {} disagree on their shared edge",
+                region, neighbor
+            ),
+            Issue::Overlap {
+                region,
+                other,
+                area,
+            } => write!(f, "regions {} and {} overlap by {}", region, other, area),
+            Issue::Coverage { covered, expected } => write!(
+                f,
+                "regions cover {} of the {} boundary area",
+                covered, expected
+            ),
+            Issue::BadSetting { name } => write!(f, "setting {} is out of range", name),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValidationReport {
+    pub regions: usize,
+    pub issues: Vec<Issue>,
+}
+
+impl ValidationReport {
+    pub fn is_valid(&self) -> bool {
+        self.issues.is_empty()
+    }
+
+    /// Panics with the whole report, for tests.
+    #[track_caller]
+    pub fn assert_valid(&self) {
+        assert!(self.is_valid(), "invalid map:\n{}", self);
+    }
+}
+
+impl fmt::Display for ValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} regions, {} issues", self.regions, self.issues.len())?;
+        for issue in &self.issues {
+            writeln!(f, "  {}", issue)?;
+        }
+        Ok(())
+    }
+}
+
+impl Map {
+    pub fn validate(&self) -> ValidationReport {
+        self.validate_with(&ValidationOptions::default())
+    }
+
+    pub fn validate_with(&self, options: &ValidationOptions) -> ValidationReport {
+        let regions = self.get_regions();
+        let mut issues = vec![];
+        let boundary = self.get_boundary();
+        let (min, max) = self.clip_rect();
+
+        let polygons: Vec<Vec<Vec2>> = regions.iter().map(|region| region.polygon()).collect();
+        let areas: Vec<f32> = polygons
+            .iter()
+            .map(|polygon| polygon_area(polygon))
+            .collect();
+        // regions whose shape is sound enough to look for overlaps between them
+        let mut solid = vec![false; regions.len()];
+
+        // the ring of sites placed outside the boundary only closes the cells along the edge, its
+        // own cells are left open
+        let inner: Vec<bool> = regions
+            .iter()
+            .map(|region| {
+                let site = region.site;
+                site.x >= min.x && site.y >= min.y && site.x <= max.x && site.y <= max.y
+            })
+            .collect();
+        let inner_count = inner.iter().filter(|&&inner| inner).count().max(1);
+        let cell = (boundary.width() * boundary.height() / inner_count as f32).sqrt();
+        let reach = Vec2::repeat(options.max_overhang * cell);
+        let (min, max) = (min - reach, max + reach);
+        let winding = (0..regions.len())
+            .filter(|&i| inner[i])
+            .map(|i| areas[i])
+            .sum::<f32>()
+            .signum();
+
+        for (i, region) in regions.iter().enumerate() {
+            let finite = region.site.iter().all(|c| c.is_finite())
+                && region.elevation.is_finite()
+                && region.moisture.is_finite()
+                && region
+                    .vertices
+                    .iter()
+                    .all(|vertex| vertex.position().iter().all(|c| c.is_finite()));
+            if !finite {
+                issues.push(Issue::NotFinite { region: i });
+                continue;
+            }
+            if !inner[i] {
+                continue;
+            }
+
+            for (k, &neighbor) in region.neighbors.iter().enumerate() {
+                if neighbor >= regions.len() || neighbor == i {
+                    issues.push(Issue::BadNeighbor {
+                        region: i,
+                        neighbor,
+                    });
+                    continue;
+                }
+                let Some(back) = regions[neighbor].neighbors.iter().position(|&n| n == i) else {
+                    issues.push(Issue::OneWayNeighbor {
+                        region: i,
+                        neighbor,
+                    });
+                    continue;
+                };
+                if inner[neighbor] && neighbor < i {
+                    continue;
+                }
+                let (a, b) = region.edge(k);
+                let (c, d) = regions[neighbor].edge(back);
+                let slack = options.tolerance * (b - a).norm().max(1.);
+                let shared = |p: Vec2, q: Vec2| self.same_point(p, q, slack);
+                if !(shared(a, d) && shared(b, c) || shared(a, c) && shared(b, d)) {
+                    issues.push(Issue::UnsharedEdge {
+                        region: i,
+                        neighbor,
+                    });
+                }
+            }
+
+            let corners = &polygons[i];
+            if corners.len() < 3 || areas[i] == 0. {
+                issues.push(Issue::Degenerate { region: i });
+                continue;
+            }
+            solid[i] = true;
+            let size = areas[i].abs().sqrt();
+            let count = region.neighbors.len();
+            for k in 0..count {
+                let end = region.edge(k).1;
+                let start = region.edge((k + 1) % count).0;
+                if (start - end).norm() > options.tolerance * size {
+                    issues.push(Issue::OpenPolygon {
+                        region: i,
+                        edge: (k + 1) % count,
+                    });
+                }
+            }
+            if areas[i].signum() != winding {
+                issues.push(Issue::WrongWinding { region: i });
+            }
+            let n = corners.len();
+            for k in 0..n {
+                let (a, b, c) = (corners[k], corners[(k + 1) % n], corners[(k + 2) % n]);
+                let (u, v) = (b - a, c - b);
+                let turn = (u.x * v.y - u.y * v.x) * winding;
+                if turn < -options.tolerance * u.norm() * v.norm() {
+                    issues.push(Issue::NotConvex {
+                        region: i,
+                        corner: b,
+                    });
+                    break;
+                }
+            }
+            let outside = corners.iter().find(|corner| {
+                corner.x < min.x || corner.y < min.y || corner.x > max.x || corner.y > max.y
+            });
+            if let Some(&corner) = outside {
+                issues.push(Issue::OutsideBoundary { region: i, corner });
+            }
+        }
+
+        issues.extend(self.overlaps(&polygons, &areas, &solid, options));
+        let covered: f32 = regions.iter().map(|region| self.area_inside(region)).sum();
+        let expected = boundary.width() * boundary.height();
+        if (covered - expected).abs() > options.tolerance * expected {
+            issues.push(Issue::Coverage { covered, expected });
+        }
+
+        ValidationReport {
+            regions: regions.len(),
+            issues,
+        }
+    }
+
+    /// Pairs of `solid` regions sharing more than an edge. The regions are swept from left to
+    /// right so only the ones whose bounding boxes meet get intersected.
+    fn overlaps(
+        &self,
+        polygons: &[Vec<Vec2>],
+        areas: &[f32],
+        solid: &[bool],
+        options: &ValidationOptions,
+    ) -> Vec<Issue> {
+        let bounds: Vec<(Vec2, Vec2)> = polygons
+            .iter()
+            .map(|polygon| {
+                let min = polygon
+                    .iter()
+                    .fold(Vec2::repeat(f32::INFINITY), |a, b| a.inf(b));
+                let max = polygon
+                    .iter()
+                    .fold(Vec2::repeat(f32::NEG_INFINITY), |a, b| a.sup(b));
+                (min, max)
+            })
+            .collect();
+        let mut order: Vec<RegionIndex> = (0..polygons.len()).filter(|&i| solid[i]).collect();
+        order.sort_by(|&a, &b| bounds[a].0.x.total_cmp(&bounds[b].0.x));
+        let mut issues = vec![];
+        for (k, &i) in order.iter().enumerate() {
+            for &j in &order[k + 1..] {
+                if bounds[j].0.x >= bounds[i].1.x {
+                    break;
+                }
+                if bounds[j].0.y >= bounds[i].1.y || bounds[i].0.y >= bounds[j].1.y {
+                    continue;
+                }
+                let area = polygon_area(&clip_convex(&polygons[i], &polygons[j])).abs();
+                if area > options.tolerance * areas[i].abs().min(areas[j].abs()) {
+                    issues.push(Issue::Overlap {
+                        region: i.min(j),
+                        other: i.max(j),
+                        area,
+                    });
+                }
+            }
+        }
+        issues
+    }
+
+    /// Equal within `slack`, or across a wrapping edge.
+    fn same_point(&self, p: Vec2, q: Vec2, slack: f32) -> bool {
+        self.tile_offsets()
+            .iter()
+            .any(|offset| (p + offset - q).norm() <= slack)
+    }
+}
+
+/// Part of `subject` inside the convex polygon `clip`, whichever way it's wound.
+fn clip_convex(subject: &[Vec2], clip: &[Vec2]) -> Vec<Vec2> {
+    let winding = polygon_area(clip).signum();
+    let mut output = subject.to_vec();
+    for k in 0..clip.len() {
+        let (a, b) = (clip[k], clip[(k + 1) % clip.len()]);
+        let side = |p: Vec2| ((b.x - a.x) * (p.y - a.y) - (b.y - a.y) * (p.x - a.x)) * winding;
+        let input = std::mem::take(&mut output);
+        for (n, &p) in input.iter().enumerate() {
+            let q = input[(n + 1) % input.len()];
+            let (sp, sq) = (side(p), side(q));
+            if sp >= 0. {
+                output.push(p);
+            }
+            if (sp >= 0.) != (sq >= 0.) {
+                output.push(p + (q - p) * (sp / (sp - sq)));
+            }
+        }
+    }
+    output
+}
+
+/// `generate_map` followed by `Map::validate`, for callers that would rather not use a broken
+/// map. The report of a broken one comes back as `MapError::Invalid`.
+pub fn generate_valid_map(settings: MapSettings) -> Result<Map, MapError> {
+    let map = generate_map(settings)?;
+    let report = map.validate();
+    if report.is_valid() {
+        Ok(map)
+    } else {
+        Err(MapError::Invalid(report))
+    }
+}
+
+#[cfg(test)]
+mod validate_tests {
+    use super::*;
+    use crate::map::{test_map, test_settings, DistanceFn, ReshapingFn, Wrap};
+    use crate::voronoi::VoronoiVertex;
+    use crate::Boundary;
+
+    #[test]
+    fn test_generated_maps_are_valid() {
+        for wrap in [Wrap::None, Wrap::Horizontal, Wrap::Both] {
+            generate_map(test_settings().with_site_count(1000).with_wrap(wrap))
+                .unwrap()
+                .validate()
+                .assert_valid();
+        }
+        assert!(generate_valid_map(test_settings().with_wrap(Wrap::Both)).is_ok());
+        test_map(Wrap::None).validate().assert_valid();
+    }
+
+    #[test]
+    fn test_valid_at_any_cell_size() {
+        let sized = |x: f32, y: f32, width: f32, height: f32| {
+            let boundary = Boundary::from_top_left(Vec2::new(x, y), width, height);
+            MapSettings::new(boundary, 7, DistanceFn::Diagonal, ReshapingFn::Flat)
+        };
+        let cases = [
+            sized(-320., 320., 640., 640.).with_site_count(1024),
+            sized(-320., 320., 640., 640.).with_site_count(4096),
+            test_settings().with_site_count(64),
+            test_settings().with_site_count(16),
+            sized(-32., 4., 64., 8.)
+                .with_site_count(8)
+                .with_wrap(Wrap::Horizontal),
+            sized(-50., 15., 100., 30.)
+                .with_site_count(300)
+                .with_wrap(Wrap::Horizontal),
+        ];
+        for settings in cases {
+            generate_map(settings.clone())
+                .unwrap()
+                .validate()
+                .assert_valid();
+            assert!(generate_valid_map(settings).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_reports_corruption() {
+        let map = generate_map(test_settings().with_site_count(400)).unwrap();
+        let inner = |i: usize| {
+            let site = map.get_regions()[i].site;
+            site.x.abs() < 28. && site.y.abs() < 28.
+        };
+        let a = (0..map.get_regions().len()).find(|&i| inner(i)).unwrap();
+        let b = (a + 1..map.get_regions().len())
+            .find(|&i| inner(i) && !map.get_regions()[a].neighbors.contains(&i))
+            .unwrap();
+        let mut regions = map.get_regions().clone();
+        regions[a].vertices.reverse();
+        regions[b].neighbors.push(a);
+        regions[b].moisture = f32::NAN;
+        let neighbor = regions[a].neighbors[0];
+        regions[neighbor].neighbors.retain(|&n| n != a);
+        let corrupted = Map::from_regions(map.get_settings().clone(), regions, vec![]).unwrap();
+
+        let report = corrupted.validate();
+        assert!(!report.is_valid());
+        assert!(report.issues.contains(&Issue::WrongWinding { region: a }));
+        assert!(report.issues.contains(&Issue::NotFinite { region: b }));
+        assert!(report.issues.contains(&Issue::OneWayNeighbor {
+            region: a,
+            neighbor
+        }));
+        assert!(report
+            .to_string()
+            .starts_with(&format!("{} regions, ", map.get_regions().len())));
+    }
+
+    #[test]
+    fn test_reports_overlap() {
+        // moving a cell leaves a gap as large as the overlap, the covered area stays the same
+        let map = test_map(Wrap::None);
+        let moved = (0..map.get_regions().len())
+            .find(|&i| map.get_regions()[i].site.norm() < 10.)
+            .unwrap();
+        let mut regions = map.get_regions().clone();
+        for vertex in &mut regions[moved].vertices {
+            let (VoronoiVertex::Inner(point) | VoronoiVertex::Outer(_, point)) = vertex;
+            *point += Vec2::new(0.3, 0.);
+        }
+        let corrupted = Map::from_regions(map.get_settings().clone(), regions, vec![]).unwrap();
+
+        let report = corrupted.validate();
+        assert!(report
+            .issues
+            .iter()
+            .any(|issue| matches!(issue, Issue::Overlap { region, other, .. }
+                if *region == moved || *other == moved)));
+        assert!(!report
+            .issues
+            .iter()
+            .any(|issue| matches!(issue, Issue::Coverage { .. })));
+    }
+}
@@ -22,15 +22,28 @@ pub enum VoronoiVertex {
     Outer(OuterType, Vec2),
 }
 
+impl VoronoiVertex {
+    pub fn position(&self) -> Vec2 {
+        match self {
+            VoronoiVertex::Inner(pt) | VoronoiVertex::Outer(_, pt) => *pt,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct VoronoiRegion {
     pub(crate) site: Vec2,
     pub(crate) vertices: Vec<VoronoiVertex>,
+    pub(crate) neighbors: Vec<usize>,
 }
 
 impl VoronoiRegion {
-    pub fn new(site: Vec2, vertices: Vec<VoronoiVertex>) -> Self {
-        Self { site, vertices }
+    pub fn new(site: Vec2, vertices: Vec<VoronoiVertex>, neighbors: Vec<usize>) -> Self {
+        Self {
+            site,
+            vertices,
+            neighbors,
+        }
     }
 
     pub fn site(&self) -> Vec2 {
@@ -40,6 +53,10 @@ impl VoronoiRegion {
     pub fn vertices(&self) -> &Vec<VoronoiVertex> {
         &self.vertices
     }
+
+    pub fn neighbors(&self) -> &Vec<usize> {
+        &self.neighbors
+    }
 }
 
 pub struct VoronoiEdge {
@@ -0,0 +1,393 @@
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::ops::Range;
+
+use nalgebra_glm::Vec2;
+
+use crate::error::MapError;
+use crate::map::{
+    build_map, terrain_noise, DistanceFn, Map, MapSettings, RegionIndex, ReshapingFn, Wrap,
+};
+use crate::names::splitmix64;
+use crate::validate::{Issue, ValidationReport};
+use crate::Boundary;
+
+pub type ChunkCoord = (i32, i32);
+/// Cell of the world wide jittered grid, each one holds exactly one site.
+pub type GridCell = (i64, i64);
+
+const JITTER: f32 = 0.5;
+/// Same scale `new_map` samples its noise at.
+const NOISE_SCALE: f32 = 64.;
+/// Fewest rings of borrowed cells for the regions along the chunk edges to be exact.
+const MIN_MARGIN: u32 = 3;
+
+#[derive(Debug, Clone)]
+pub struct WorldSettings {
+    pub seed: u64,
+    /// Number of grid cells along each side of a chunk.
+    pub chunk_cells: u32,
+    /// Size of a grid cell in world units.
+    pub cell_size: f32,
+    /// Rings of cells borrowed from the neighboring chunks so the Voronoi regions along the
+    /// chunk edges come out exactly like on the other side, at least three.
+    pub margin: u32,
+}
+
+impl Default for WorldSettings {
+    fn default() -> Self {
+        Self {
+            seed: 12345,
+            chunk_cells: 32,
+            cell_size: 1.0,
+            margin: 3,
+        }
+    }
+}
+
+impl WorldSettings {
+    /// Rejects settings chunks can't be generated with, the report lists every bad setting.
+    pub fn validate(&self) -> Result<(), MapError> {
+        let mut issues = vec![];
+        let side = self.chunk_cells as u64 + 2 * self.margin as u64;
+        let fits = side
+            .checked_mul(side)
+            .and_then(|cells| usize::try_from(cells).ok())
+            .is_some();
+        if self.chunk_cells == 0 || !fits {
+            issues.push(Issue::BadSetting {
+                name: "chunk_cells",
+            });
+        }
+        if !(self.cell_size.is_finite() && self.cell_size > 0.) {
+            issues.push(Issue::BadSetting { name: "cell_size" });
+        }
+        if self.margin < MIN_MARGIN || !fits {
+            issues.push(Issue::BadSetting { name: "margin" });
+        }
+        if issues.is_empty() {
+            Ok(())
+        } else {
+            Err(MapError::Invalid(ValidationReport { regions: 0, issues }))
+        }
+    }
+
+    pub fn chunk_size(&self) -> f32 {
+        self.chunk_cells as f32 * self.cell_size
+    }
+
+    pub fn chunk_at(&self, point: Vec2) -> ChunkCoord {
+        let size = self.chunk_size();
+        (
+            (point.x / size).floor() as i32,
+            (point.y / size).floor() as i32,
+        )
+    }
+
+    pub fn chunk_boundary(&self, coord: ChunkCoord) -> Boundary {
+        let size = self.chunk_size();
+        let top_left = Vec2::new(coord.0 as f32 * size, (coord.1 + 1) as f32 * size);
+        Boundary::from_top_left(top_left, size, size)
+    }
+
+    fn chunk_of_cell(&self, cell: GridCell) -> ChunkCoord {
+        let n = self.chunk_cells as i64;
+        (cell.0.div_euclid(n) as i32, cell.1.div_euclid(n) as i32)
+    }
+
+    fn site(&self, cell: GridCell) -> Vec2 {
+        let hash = splitmix64(self.seed ^ splitmix64((cell.0 as u64) ^ splitmix64(cell.1 as u64)));
+        let jitter = |bits: u64| {
+            let a = (bits & 0xFFFF) as f32 / 65535.;
+            let b = ((bits >> 16) & 0xFFFF) as f32 / 65535.;
+            JITTER * (a - b)
+        };
+        Vec2::new(
+            (cell.0 as f32 + 0.5 + jitter(hash)) * self.cell_size,
+            (cell.1 as f32 + 0.5 + jitter(hash >> 32)) * self.cell_size,
+        )
+    }
+}
+
+/// One square of the world. Its map also holds the ghost regions borrowed from the neighbors,
+/// they come after the regions the chunk owns.
+pub struct Chunk {
+    coord: ChunkCoord,
+    map: Map,
+    cells: Vec<GridCell>,
+    owned: usize,
+}
+
+impl Chunk {
+    pub fn coord(&self) -> ChunkCoord {
+        self.coord
+    }
+
+    pub fn map(&self) -> &Map {
+        &self.map
+    }
+
+    pub fn owned_regions(&self) -> Range<RegionIndex> {
+        0..self.owned
+    }
+
+    pub fn is_ghost(&self, region: RegionIndex) -> bool {
+        region >= self.owned
+    }
+
+    pub fn cell(&self, region: RegionIndex) -> GridCell {
+        self.cells[region]
+    }
+}
+
+/// Generates a chunk on its own, the same `settings` and `coord` always give the same chunk.
+pub fn generate_chunk(settings: &WorldSettings, coord: ChunkCoord) -> Result<Chunk, MapError> {
+    settings.validate()?;
+    let n = settings.chunk_cells as i64;
+    let margin = settings.margin as i64;
+    let (x0, y0) = (coord.0 as i64 * n, coord.1 as i64 * n);
+
+    let mut cells = Vec::with_capacity(((n + 2 * margin) * (n + 2 * margin)) as usize);
+    for y in y0..y0 + n {
+        for x in x0..x0 + n {
+            cells.push((x, y));
+        }
+    }
+    let owned = cells.len();
+    for y in y0 - margin..y0 + n + margin {
+        for x in x0 - margin..x0 + n + margin {
+            if x < x0 || x >= x0 + n || y < y0 || y >= y0 + n {
+                cells.push((x, y));
+            }
+        }
+    }
+
+    let points = cells.iter().map(|&cell| settings.site(cell)).collect();
+    let map_settings = MapSettings {
+        boundary: settings.chunk_boundary(coord),
+        // the sites come from `WorldSettings::site`, not from this seed
+        site_seed: settings.seed,
+        seed: settings.seed,
+        distance_fn: DistanceFn::Diagonal,
+        reshape_fn: ReshapingFn::Flat,
+        wrap: Wrap::None,
+        site_count: None,
+    };
+    let noise = terrain_noise(settings.seed);
+    let map = build_map(map_settings, points, |site| {
+        let n = noise.get_noise(site.x / NOISE_SCALE, site.y / NOISE_SCALE);
+        ((1. + n) / 2., (1. - n) / 2.)
+    })?;
+    Ok(Chunk {
+        coord,
+        map,
+        cells,
+        owned,
+    })
+}
+
+/// Unbounded terrain made of chunks that are generated on demand. There is no island shaping,
+/// elevation only comes from noise sampled in world coordinates, so regenerate chunks through
+/// the world rather than with `Map::regenerate`.
+pub struct World {
+    settings: WorldSettings,
+    chunks: HashMap<ChunkCoord, Chunk>,
+}
+
+impl World {
+    pub fn new(settings: WorldSettings) -> Result<Self, MapError> {
+        settings.validate()?;
+        Ok(Self {
+            settings,
+            chunks: HashMap::new(),
+        })
+    }
+
+    pub fn get_settings(&self) -> &WorldSettings {
+        &self.settings
+    }
+
+    pub fn load(&mut self, coord: ChunkCoord) -> Result<&Chunk, MapError> {
+        match self.chunks.entry(coord) {
+            Entry::Occupied(entry) => Ok(entry.into_mut()),
+            Entry::Vacant(entry) => Ok(entry.insert(generate_chunk(&self.settings, coord)?)),
+        }
+    }
+
+    pub fn unload(&mut self, coord: ChunkCoord) -> Option<Chunk> {
+        self.chunks.remove(&coord)
+    }
+
+    pub fn get(&self, coord: ChunkCoord) -> Option<&Chunk> {
+        self.chunks.get(&coord)
+    }
+
+    pub fn is_loaded(&self, coord: ChunkCoord) -> bool {
+        self.chunks.contains_key(&coord)
+    }
+
+    pub fn loaded(&self) -> impl Iterator<Item = ChunkCoord> + '_ {
+        self.chunks.keys().copied()
+    }
+
+    /// Keeps loaded exactly the chunks within `radius` chunks of `center`, returns the
+    /// coordinates that were loaded and unloaded. Stops at the first chunk that fails to
+    /// generate.
+    pub fn stream_around(
+        &mut self,
+        center: Vec2,
+        radius: i32,
+    ) -> Result<(Vec<ChunkCoord>, Vec<ChunkCoord>), MapError> {
+        let (cx, cy) = self.settings.chunk_at(center);
+        let in_range =
+            |coord: &ChunkCoord| (coord.0 - cx).abs() <= radius && (coord.1 - cy).abs() <= radius;
+
+        let unloaded: Vec<ChunkCoord> = self.loaded().filter(|coord| !in_range(coord)).collect();
+        for coord in &unloaded {
+            self.unload(*coord);
+        }
+        let mut loaded = vec![];
+        for y in cy - radius..=cy + radius {
+            for x in cx - radius..=cx + radius {
+                if !self.is_loaded((x, y)) {
+                    self.load((x, y))?;
+                    loaded.push((x, y));
+                }
+            }
+        }
+        Ok((loaded, unloaded))
+    }
+
+    /// Chunk owning a region, ghost regions resolve to the chunk next door even when that
+    /// chunk is not loaded.
+    pub fn resolve(
+        &self,
+        coord: ChunkCoord,
+        region: RegionIndex,
+    ) -> Option<(ChunkCoord, RegionIndex)> {
+        let chunk = self.get(coord)?;
+        if !chunk.is_ghost(region) {
+            return Some((coord, region));
+        }
+        let cell = chunk.cell(region);
+        let owner = self.settings.chunk_of_cell(cell);
+        let n = self.settings.chunk_cells as i64;
+        let local = (cell.1 - owner.1 as i64 * n) * n + (cell.0 - owner.0 as i64 * n);
+        Some((owner, local as RegionIndex))
+    }
+
+    pub fn region_at(&self, point: Vec2) -> Option<(ChunkCoord, RegionIndex)> {
+        let coord = self.settings.chunk_at(point);
+        let region = self.get(coord)?.map().region_at(point)?;
+        self.resolve(coord, region)
+    }
+}
+
+#[cfg(test)]
+mod world_tests {
+    use super::*;
+
+    fn polygon(chunk: &Chunk, region: RegionIndex) -> Vec<Vec2> {
+        let mut points: Vec<Vec2> = chunk.map().get_regions()[region]
+            .vertices
+            .iter()
+            .map(|vertex| vertex.position())
+            .collect();
+        points.sort_by(|a, b| a.x.total_cmp(&b.x).then(a.y.total_cmp(&b.y)));
+        points
+    }
+
+    #[test]
+    fn test_chunks_are_deterministic() {
+        let settings = WorldSettings::default();
+        let a = generate_chunk(&settings, (2, -1)).unwrap();
+        let b = generate_chunk(&settings, (2, -1)).unwrap();
+        for (ra, rb) in a.map().get_regions().iter().zip(b.map().get_regions()) {
+            assert_eq!(ra.site, rb.site);
+            assert_eq!(ra.elevation, rb.elevation);
+        }
+        assert!(a.owned_regions().all(|i| a
+            .map()
+            .get_boundary()
+            .point_inside(a.map().get_regions()[i].site)));
+    }
+
+    #[test]
+    fn test_seams_match() {
+        let mut world = World::new(WorldSettings::default()).unwrap();
+        world.load((0, 0)).unwrap();
+        world.load((1, 0)).unwrap();
+        let left = world.get((0, 0)).unwrap();
+        let right = world.get((1, 0)).unwrap();
+        let mut checked = 0;
+        for region in left.owned_regions() {
+            for &neighbor in &left.map().get_regions()[region].neighbors {
+                if !left.is_ghost(neighbor) {
+                    continue;
+                }
+                let (coord, owned) = world.resolve((0, 0), neighbor).unwrap();
+                if coord != (1, 0) {
+                    continue;
+                }
+                assert_eq!(right.cell(owned), left.cell(neighbor));
+                let ghost = &left.map().get_regions()[neighbor];
+                let real = &right.map().get_regions()[owned];
+                assert_eq!(ghost.site, real.site);
+                assert_eq!(ghost.is_water(), real.is_water());
+                for (a, b) in polygon(left, neighbor).iter().zip(polygon(right, owned)) {
+                    assert!((a - b).norm() < 1e-4);
+                }
+                checked += 1;
+            }
+        }
+        assert!(checked >= 32);
+    }
+
+    #[test]
+    fn test_rejects_bad_settings() {
+        let bad = WorldSettings {
+            chunk_cells: 0,
+            margin: 1,
+            ..WorldSettings::default()
+        };
+        match World::new(bad.clone()) {
+            Err(MapError::Invalid(report)) => assert_eq!(
+                report.issues,
+                vec![
+                    Issue::BadSetting {
+                        name: "chunk_cells"
+                    },
+                    Issue::BadSetting { name: "margin" },
+                ]
+            ),
+            _ => panic!("accepted {:?}", bad),
+        }
+        assert!(generate_chunk(&bad, (0, 0)).is_err());
+        let huge = WorldSettings {
+            margin: u32::MAX,
+            ..WorldSettings::default()
+        };
+        assert!(generate_chunk(&huge, (0, 0)).is_err());
+        let flat = WorldSettings {
+            cell_size: 0.,
+            ..WorldSettings::default()
+        };
+        assert!(World::new(flat).is_err());
+    }
+
+    #[test]
+    fn test_stream_around() {
+        let mut world = World::new(WorldSettings::default()).unwrap();
+        let (loaded, unloaded) = world.stream_around(Vec2::new(5., 5.), 1).unwrap();
+        assert_eq!(loaded.len(), 9);
+        assert!(unloaded.is_empty());
+        let (loaded, unloaded) = world.stream_around(Vec2::new(40., 5.), 1).unwrap();
+        assert_eq!(loaded.len(), 3);
+        assert_eq!(unloaded.len(), 3);
+        assert!(!world.is_loaded((-1, 0)));
+        assert!(world.is_loaded((2, 0)));
+        let (coord, region) = world.region_at(Vec2::new(40.2, 5.3)).unwrap();
+        assert_eq!(coord, (1, 0));
+        assert!(region < 32 * 32);
+    }
+}
@@ -0,0 +1,90 @@
+use std::fmt;
+use std::io;
+
+use glium::backend::glutin::DisplayCreationError;
+use glium::vertex::BufferCreationError;
+use glium::{DrawError, ProgramCreationError, SwapBuffersError};
+use math::error::MapError;
+use math::political::KingdomError;
+use math::save::FileError;
+
+/// What the viewer shows in its error dialog instead of crashing.
+#[derive(Debug)]
+pub enum ViewerError {
+    /// No window to show the dialog in, returned from `main` instead.
+    Display(DisplayCreationError),
+    /// A shader source can't be read.
+    Io(String, io::Error),
+    Shader(ProgramCreationError),
+    VertexBuffer(BufferCreationError),
+    Draw(DrawError),
+    Frame(SwapBuffersError),
+    Map(MapError),
+    Kingdoms(KingdomError),
+    /// A map can't be saved to or loaded from the path.
+    File(String, FileError),
+}
+
+impl fmt::Display for ViewerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ViewerError::Display(error) => write!(f, "can't open the window: {}", error),
+            ViewerError::Io(path, error) => write!(f, "can't read {}: {}", path, error),
+            ViewerError::Shader(error) => write!(f, "the shaders don't compile: {}", error),
+            ViewerError::VertexBuffer(error) => {
+                write!(f, "can't build a vertex buffer: {}", error)
+            }
+            ViewerError::Draw(error) => write!(f, "can't draw the map: {}", error),
+            ViewerError::Frame(error) => write!(f, "can't show the frame: {}", error),
+            ViewerError::Map(error) => write!(f, "can't generate the map: {}", error),
+            ViewerError::Kingdoms(error) => {
+                write!(f, "can't split the map into kingdoms: {}", error)
+            }
+            ViewerError::File(path, error) => write!(f, "{}: {}", path, error),
+        }
+    }
+}
+
+impl std::error::Error for ViewerError {}
+
+impl From<DisplayCreationError> for ViewerError {
+    fn from(error: DisplayCreationError) -> Self {
+        ViewerError::Display(error)
+    }
+}
+
+impl From<ProgramCreationError> for ViewerError {
+    fn from(error: ProgramCreationError) -> Self {
+        ViewerError::Shader(error)
+    }
+}
+
+impl From<BufferCreationError> for ViewerError {
+    fn from(error: BufferCreationError) -> Self {
+        ViewerError::VertexBuffer(error)
+    }
+}
+
+impl From<DrawError> for ViewerError {
+    fn from(error: DrawError) -> Self {
+        ViewerError::Draw(error)
+    }
+}
+
+impl From<SwapBuffersError> for ViewerError {
+    fn from(error: SwapBuffersError) -> Self {
+        ViewerError::Frame(error)
+    }
+}
+
+impl From<MapError> for ViewerError {
+    fn from(error: MapError) -> Self {
+        ViewerError::Map(error)
+    }
+}
+
+impl From<KingdomError> for ViewerError {
+    fn from(error: KingdomError) -> Self {
+        ViewerError::Kingdoms(error)
+    }
+}
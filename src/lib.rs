@@ -3,13 +3,18 @@ use egui_glium::EguiGlium;
 use glium::uniforms::{UniformValue, Uniforms};
 use glium::DrawParameters;
 use math::color::PresetColors;
-use math::map::{DistanceFn, ReshapingFn};
+use math::map::{DistanceFn, MapSettings, ReshapingFn, Wrap};
+use math::progress::Progress;
+use math::Boundary;
 use std::collections::HashMap;
-use std::fs::File;
-use std::io::Read;
+use std::fs;
 
+use crate::error::ViewerError;
+
+pub mod error;
 pub mod pipeline;
 pub mod tick;
+pub mod worker;
 
 pub fn draw_params() -> DrawParameters<'static> {
     use glium::{BackfaceCullingMode, Depth, DepthTest};
@@ -25,13 +30,8 @@ pub fn draw_params() -> DrawParameters<'static> {
     }
 }
 
-pub fn load_glsl(path: &str) -> String {
-    let mut nice_shader = String::new();
-    File::open(path)
-        .unwrap()
-        .read_to_string(&mut nice_shader)
-        .unwrap();
-    nice_shader
+pub fn load_glsl(path: &str) -> Result<String, ViewerError> {
+    fs::read_to_string(path).map_err(|error| ViewerError::Io(path.to_string(), error))
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -87,6 +87,14 @@ impl Uniforms for UniformStorage<'_> {
     }
 }
 
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum ColorMode {
+    Biome,
+    Kingdoms,
+    CoastDistance,
+    TravelTime,
+}
+
 pub struct State {
     pub open_debug: bool,
     pub background_color: [f32; 4],
@@ -97,6 +105,20 @@ pub struct State {
     pub reshape_fn: ReshapingFn,
     pub distance_fn: DistanceFn,
     pub seed: u64,
+    pub site_seed: u64,
+    pub color_mode: ColorMode,
+    pub kingdoms: usize,
+    pub wrap: Wrap,
+    /// Only set by loaded maps, see `MapSettings::site_count`.
+    pub site_count: Option<usize>,
+    pub map_path: String,
+    pub save: bool,
+    pub load: bool,
+    /// Latest report of the generation running, if any.
+    pub progress: Option<Progress>,
+    pub cancel: bool,
+    /// Shown in a dialog until it's dismissed.
+    pub error: Option<ViewerError>,
 }
 
 impl Default for State {
@@ -111,6 +133,52 @@ impl Default for State {
             reshape_fn: ReshapingFn::Flat,
             distance_fn: DistanceFn::Diagonal,
             seed: 12345,
+            site_seed: 12345,
+            color_mode: ColorMode::Biome,
+            kingdoms: 6,
+            wrap: Wrap::None,
+            site_count: None,
+            map_path: "map.json".to_string(),
+            save: false,
+            load: false,
+            progress: None,
+            cancel: false,
+            error: None,
+        }
+    }
+}
+
+impl State {
+    pub fn map_settings(&self, boundary: Boundary) -> MapSettings {
+        MapSettings {
+            boundary,
+            site_seed: self.site_seed,
+            seed: self.seed,
+            distance_fn: self.distance_fn,
+            reshape_fn: self.reshape_fn,
+            wrap: self.wrap,
+            site_count: self.site_count,
+        }
+    }
+
+    /// Makes the widgets match a map that was not generated from them, like a loaded one.
+    pub fn apply_settings(&mut self, settings: &MapSettings) {
+        self.site_seed = settings.site_seed;
+        self.seed = settings.seed;
+        self.distance_fn = settings.distance_fn;
+        self.reshape_fn = settings.reshape_fn;
+        self.wrap = settings.wrap;
+        self.site_count = settings.site_count;
+    }
+
+    /// The value of `result`, its error goes to the dialog.
+    pub fn report<T>(&mut self, result: Result<T, ViewerError>) -> Option<T> {
+        match result {
+            Ok(value) => Some(value),
+            Err(error) => {
+                self.error = Some(error);
+                None
+            }
         }
     }
 }
@@ -175,6 +243,33 @@ fn show_widgets(ui: &mut Ui, state: &mut State) {
         );
     });
     ui.end_row();
+    ui.add(label("Wrap"));
+    egui::ComboBox::from_id_source("wrap").show_ui(ui, |ui| {
+        ui.selectable_value(&mut state.wrap, Wrap::None, "None");
+        ui.selectable_value(&mut state.wrap, Wrap::Horizontal, "Horizontal");
+        ui.selectable_value(&mut state.wrap, Wrap::Both, "Both");
+    });
+    ui.end_row();
+    ui.add(label("Colors"));
+    egui::ComboBox::from_id_source("colormode").show_ui(ui, |ui| {
+        ui.selectable_value(&mut state.color_mode, ColorMode::Biome, "Biome");
+        ui.selectable_value(&mut state.color_mode, ColorMode::Kingdoms, "Kingdoms");
+        ui.selectable_value(
+            &mut state.color_mode,
+            ColorMode::CoastDistance,
+            "Coast distance",
+        );
+        ui.selectable_value(&mut state.color_mode, ColorMode::TravelTime, "Travel time");
+    });
+    ui.end_row();
+    if matches!(
+        state.color_mode,
+        ColorMode::Kingdoms | ColorMode::TravelTime
+    ) {
+        ui.add(label("Kingdoms"));
+        ui.add(egui::Slider::new(&mut state.kingdoms, 1..=24));
+        ui.end_row();
+    }
 }
 
 pub fn show_window(egui: &mut EguiGlium, state: &mut State) {
@@ -185,6 +280,30 @@ pub fn show_window(egui: &mut EguiGlium, state: &mut State) {
             }
             ui.add(label("Seed"));
             ui.add(egui::Slider::new(&mut state.seed, 1..=2u64.pow(16)).logarithmic(true));
+            ui.add(label("Sites"));
+            ui.add(egui::Slider::new(&mut state.site_seed, 1..=2u64.pow(16)).logarithmic(true));
+            ui.add(label("File"));
+            ui.text_edit_singleline(&mut state.map_path);
+            if ui.button("Save").clicked() {
+                state.save = true;
+            }
+            if ui.button("Load").clicked() {
+                state.load = true;
+            }
+            if let Some(progress) = state.progress {
+                ui.add(
+                    egui::ProgressBar::new(progress.fraction)
+                        .desired_width(200.)
+                        .text(format!(
+                            "{} {:.0}%",
+                            progress.stage.name(),
+                            progress.fraction * 100.
+                        )),
+                );
+                if ui.button("Cancel").clicked() {
+                    state.cancel = true;
+                }
+            }
         });
     });
     SidePanel::left("my_side_panel")
@@ -198,4 +317,19 @@ pub fn show_window(egui: &mut EguiGlium, state: &mut State) {
                     show_widgets(ui, state);
                 });
         });
+    if let Some(error) = &state.error {
+        let mut dismissed = false;
+        egui::Window::new("Error")
+            .collapsible(false)
+            .resizable(false)
+            .show(egui.ctx(), |ui| {
+                ui.label(error.to_string());
+                if ui.button("OK").clicked() {
+                    dismissed = true;
+                }
+            });
+        if dismissed {
+            state.error = None;
+        }
+    }
 }
@@ -1,55 +1,148 @@
+use std::sync::Arc;
+
 use egui_glium::EguiGlium;
 use glium::glutin::dpi::{PhysicalSize, Size};
 use glium::glutin::window::WindowBuilder;
 use glium::glutin::GlProfile;
 use glium::uniforms::AsUniformValue;
 use glium::Surface;
-use math::color::PresetColors;
+use math::color::{heat_map, PresetColors, HSL};
 use math::glm::{vec3, Vec2};
-use math::map::{new_map, Map};
+use math::graph::CostField;
+use math::map::{generate_map, Map};
+use math::political::{expand_kingdoms, pick_capitals, ExpansionCosts, KingdomError, OwnerId};
+use math::save::{load_map, save_map};
 use math::voronoi::VoronoiVertex::{self, Inner, Outer};
 use math::{float_eq, Boundary, CameraSystem, Ortho, RawMat4, TransformBuilder};
 use ui::winit::event::{Event, StartCause};
 use ui::winit::event_loop::ControlFlow;
 use ui::{Binding, Gesture, Input, LoopType};
+use MapGenerator::error::ViewerError;
 use MapGenerator::pipeline::{RegionPipeline, SitePipeline, WirePipeline};
 use MapGenerator::tick::{
     TickSystem, TICK_DRAW_ID, TICK_FRAME_ID, TICK_RENDER_EGUI_ID, TICK_RENDER_ID,
 };
-use MapGenerator::{draw_params, show_window, State, UniformStorage, VertexColor};
+use MapGenerator::worker::MapWorker;
+use MapGenerator::{draw_params, show_window, ColorMode, State, UniformStorage, VertexColor};
 
 const WIDTH: f32 = 1920f32;
 const HEIGHT: f32 = 1080f32;
 
-fn extract_region_mesh(map: &Map) -> Vec<VertexColor> {
+/// Wrapping maps are repeated around themselves so the seams can be checked.
+fn extract_region_mesh(map: &Map, colors: &[[f32; 3]]) -> Vec<VertexColor> {
     let mut meshes_vertices = vec![];
-    for region in map.get_regions() {
-        let site = region.site;
+    for offset in map.tile_offsets() {
+        extract_tile_mesh(map, colors, offset, &mut meshes_vertices);
+    }
+    meshes_vertices
+}
+
+fn extract_tile_mesh(
+    map: &Map,
+    colors: &[[f32; 3]],
+    offset: Vec2,
+    meshes_vertices: &mut Vec<VertexColor>,
+) {
+    for (region, &color) in map.get_regions().iter().zip(colors) {
+        let site = region.site + offset;
         let region_vertices = &region.vertices;
         for (i, vertice) in region_vertices.iter().enumerate() {
             let v2_index = (i + 1) % (region_vertices.len() - 1);
             let v1 = match vertice {
-                VoronoiVertex::Inner(pt) | VoronoiVertex::Outer(_, pt) => *pt,
+                VoronoiVertex::Inner(pt) | VoronoiVertex::Outer(_, pt) => *pt + offset,
             };
             let v2 = match region_vertices[v2_index] {
-                VoronoiVertex::Inner(pt) | VoronoiVertex::Outer(_, pt) => *pt,
+                VoronoiVertex::Inner(pt) | VoronoiVertex::Outer(_, pt) => *pt + offset,
             };
-            meshes_vertices.push(VertexColor::new(site.x, site.y, 0.0, region.color));
-            meshes_vertices.push(VertexColor::new(v1.x, v1.y, 0.0, region.color));
-            meshes_vertices.push(VertexColor::new(v2.x, v2.y, 0.0, region.color));
+            meshes_vertices.push(VertexColor::new(site.x, site.y, 0.0, color));
+            meshes_vertices.push(VertexColor::new(v1.x, v1.y, 0.0, color));
+            meshes_vertices.push(VertexColor::new(v2.x, v2.y, 0.0, color));
         }
     }
-    meshes_vertices
 }
 
-fn main() {
+fn region_mesh(map: &Map, state: &State) -> Result<Vec<VertexColor>, ViewerError> {
+    Ok(extract_region_mesh(map, &region_colors(map, state)?))
+}
+
+fn region_colors(map: &Map, state: &State) -> Result<Vec<[f32; 3]>, KingdomError> {
+    let colors = match state.color_mode {
+        ColorMode::Biome => map
+            .get_regions()
+            .iter()
+            .map(|region| region.color)
+            .collect(),
+        ColorMode::Kingdoms => {
+            let capitals = pick_capitals(map, state.kingdoms, map.get_seed());
+            let kingdoms = expand_kingdoms(map, &capitals, &ExpansionCosts::default())?;
+            kingdoms
+                .owners
+                .iter()
+                .zip(map.get_regions())
+                .map(|(owner, region)| match owner {
+                    Some(owner) => owner_color(*owner),
+                    None => region.color,
+                })
+                .collect()
+        }
+        ColorMode::CoastDistance => {
+            let field = map.cost_field(&map.coast(), f32::INFINITY, |a, b| {
+                Some(map.wrapped_delta(a.site, b.site).norm())
+            });
+            heat_colors(&field)
+        }
+        ColorMode::TravelTime => {
+            let capitals: Vec<_> = pick_capitals(map, state.kingdoms, map.get_seed())
+                .iter()
+                .map(|capital| capital.region)
+                .collect();
+            let costs = ExpansionCosts::default();
+            let field =
+                map.cost_field(&capitals, f32::INFINITY, |a, b| Some(costs.step(map, a, b)));
+            heat_colors(&field)
+        }
+    };
+    Ok(colors)
+}
+
+fn heat_colors(field: &CostField) -> Vec<[f32; 3]> {
+    field
+        .normalized()
+        .into_iter()
+        .map(|value| match value {
+            Some(value) => heat_map(value).into(),
+            None => PresetColors::GREY.into(),
+        })
+        .collect()
+}
+
+fn owner_color(owner: OwnerId) -> [f32; 3] {
+    let hue = (owner * 137) % 360;
+    HSL::new(hue as u16, 0.55, 0.55).to_rgb().into()
+}
+
+fn main() -> Result<(), ViewerError> {
     let mut zoom_factor = 0.0;
     let mut state = State::default();
     let boundary = Boundary::from_top_left(Vec2::new(-32.0, 32.0), 64., 64.);
-    let seed = 12345;
-    let mut map = new_map(boundary, seed, state.distance_fn, state.reshape_fn);
-    let (voronoi_sites, voronoi_wires) = setup_wires_and_sites_vertexes(&map);
-    let region_vertexes = extract_region_mesh(&map);
+    // without a map the window stays empty, the dialog tells why and Regenerate tries again
+    let generated = generate_map(state.map_settings(boundary.clone()));
+    let mut map = state
+        .report(generated.map_err(ViewerError::from))
+        .map(Arc::new);
+    let (voronoi_sites, voronoi_wires) = match &map {
+        Some(map) => setup_wires_and_sites_vertexes(map),
+        None => (vec![], vec![]),
+    };
+    let region_vertexes = match &map {
+        Some(map) => {
+            let mesh = region_mesh(map, &state);
+            state.report(mesh).unwrap_or_default()
+        }
+        None => vec![],
+    };
+    let mut applied_colors = (state.color_mode, state.kingdoms);
+    let mut worker = MapWorker::default();
     let mut camera_speed = 50.0f32;
     let draw_params = draw_params();
     let mut tick_system = TickSystem::new();
@@ -64,14 +157,15 @@ fn main() {
             HEIGHT as u32,
         )));
     let cb = glium::glutin::ContextBuilder::new().with_gl_profile(GlProfile::Core);
-    let display = glium::Display::new(wb, cb, &event_loop).unwrap();
+    let display = glium::Display::new(wb, cb, &event_loop)?;
     let mut egui = EguiGlium::new(&display);
     let mut input = Input::create();
     let binding = Binding::create();
 
-    let mut region_pipeline = RegionPipeline::new(region_vertexes, &display);
-    let mut site_pipeline = SitePipeline::new(voronoi_sites, &display);
-    let mut wire_pipeline = WirePipeline::new(voronoi_wires, &display);
+    // a pipeline that can't be built or drawn is left out of the drawing, the dialog tells why
+    let mut region_pipeline = state.report(RegionPipeline::new(region_vertexes, &display));
+    let mut site_pipeline = state.report(SitePipeline::new(voronoi_sites, &display));
+    let mut wire_pipeline = state.report(WirePipeline::new(voronoi_wires, &display));
 
     let map_model = TransformBuilder::new().scale(0.5, 0.5, 0.5).build();
 
@@ -113,38 +207,41 @@ fn main() {
             frame.clear_color_and_depth(bgc, 1.);
             let view_pos: [f32; 3] = camera.pos.into();
             let view: RawMat4 = camera.view().into();
-            {
+            if let Some(pipeline) = &region_pipeline {
                 let model = map_model.get_raw();
                 let mut my_storage = UniformStorage::default();
                 my_storage.add("vp", pre_vp.as_uniform_value());
                 my_storage.add("view", view.as_uniform_value());
                 my_storage.add("model", model.as_uniform_value());
                 my_storage.add("viewPos", view_pos.as_uniform_value());
-                region_pipeline
-                    .draw(&mut frame, &my_storage, &draw_params)
-                    .unwrap();
+                let drawn = pipeline.draw(&mut frame, &my_storage, &draw_params);
+                if state.report(drawn.map_err(ViewerError::from)).is_none() {
+                    region_pipeline = None;
+                }
             }
-            if state.show_sites {
+            if let Some(pipeline) = site_pipeline.as_ref().filter(|_| state.show_sites) {
                 let model = map_model.get_raw();
                 let mut my_storage = UniformStorage::default();
                 my_storage.add("vp", pre_vp.as_uniform_value());
                 my_storage.add("view", view.as_uniform_value());
                 my_storage.add("model", model.as_uniform_value());
                 my_storage.add("viewPos", view_pos.as_uniform_value());
-                site_pipeline
-                    .draw(&mut frame, &my_storage, &draw_params)
-                    .unwrap();
+                let drawn = pipeline.draw(&mut frame, &my_storage, &draw_params);
+                if state.report(drawn.map_err(ViewerError::from)).is_none() {
+                    site_pipeline = None;
+                }
             }
-            {
+            if let Some(pipeline) = &wire_pipeline {
                 let model = map_model.get_raw();
                 let mut my_storage = UniformStorage::default();
                 my_storage.add("vp", pre_vp.as_uniform_value());
                 my_storage.add("view", view.as_uniform_value());
                 my_storage.add("model", model.as_uniform_value());
                 my_storage.add("viewPos", view_pos.as_uniform_value());
-                wire_pipeline
-                    .draw(&mut frame, &my_storage, &draw_params)
-                    .unwrap();
+                let drawn = pipeline.draw(&mut frame, &my_storage, &draw_params);
+                if state.report(drawn.map_err(ViewerError::from)).is_none() {
+                    wire_pipeline = None;
+                }
             }
 
             tick_system.start_tick(TICK_RENDER_EGUI_ID);
@@ -152,7 +249,7 @@ fn main() {
             egui.paint(&display, &mut frame, shapes);
             tick_system.end_tick(TICK_RENDER_EGUI_ID);
 
-            frame.finish().unwrap();
+            state.report(frame.finish().map_err(ViewerError::from));
             tick_system.end_tick(TICK_RENDER_ID);
             // tick_system.debug_tick(TICK_RENDER_ID);
         }
@@ -214,6 +311,94 @@ fn main() {
                     // println!("{}", camera.pos);
                 }
             }
+            let shown = map.as_ref().map(|map| map.get_settings());
+            if let Some(settings) = worker.requested().or(shown) {
+                if settings.distance_fn != state.distance_fn
+                    || settings.reshape_fn != state.reshape_fn
+                    || settings.wrap != state.wrap
+                {
+                    state.regenerate = true;
+                }
+            }
+            let mut rebuild = false;
+            if state.load {
+                state.load = false;
+                match load_map(&state.map_path) {
+                    Ok(loaded) => {
+                        worker.discard();
+                        state.apply_settings(loaded.get_settings());
+                        map = Some(Arc::new(loaded));
+                        rebuild = true;
+                    }
+                    Err(error) => {
+                        state.error = Some(ViewerError::File(state.map_path.clone(), error));
+                    }
+                }
+            }
+            if state.save {
+                state.save = false;
+                if let Some(Err(error)) = map.as_ref().map(|map| save_map(map, &state.map_path)) {
+                    state.error = Some(ViewerError::File(state.map_path.clone(), error));
+                }
+            }
+            if state.cancel {
+                state.cancel = false;
+                worker.cancel();
+            }
+            if state.regenerate {
+                state.regenerate = false;
+                let boundary = match &map {
+                    Some(map) => map.get_boundary().clone(),
+                    None => boundary.clone(),
+                };
+                worker.request(map.as_ref(), state.map_settings(boundary));
+            }
+            match worker.poll() {
+                Some(Ok(generated)) => {
+                    map = Some(Arc::new(generated));
+                    rebuild = true;
+                }
+                Some(Err(error)) => {
+                    // a cancelled request is reported too, the dialog confirms it stopped
+                    state.error = Some(error.into());
+                    // back to the settings of the map still shown
+                    if let Some(map) = &map {
+                        state.apply_settings(map.get_settings());
+                    }
+                }
+                None => {}
+            }
+            state.progress = worker.progress();
+            if let Some(map) = map.as_ref().filter(|_| rebuild) {
+                let regions_vertexes = region_mesh(map, &state);
+                let (sites_vertexes, wires_vertexes) = setup_wires_and_sites_vertexes(map);
+                if let Some(pipeline) = &mut region_pipeline {
+                    if let Some(vertexes) = state.report(regions_vertexes) {
+                        let updated = pipeline.update_vertexes(&display, vertexes);
+                        state.report(updated);
+                    }
+                }
+                if let Some(pipeline) = &mut site_pipeline {
+                    let updated = pipeline.update_vertexes(&display, sites_vertexes);
+                    state.report(updated);
+                }
+                if let Some(pipeline) = &mut wire_pipeline {
+                    let updated = pipeline.update_vertexes(&display, wires_vertexes);
+                    state.report(updated);
+                }
+                applied_colors = (state.color_mode, state.kingdoms);
+            }
+            let recolor = applied_colors != (state.color_mode, state.kingdoms);
+            if let Some(map) = map.as_ref().filter(|_| recolor) {
+                applied_colors = (state.color_mode, state.kingdoms);
+                let regions_vertexes = region_mesh(map, &state);
+                if let Some(pipeline) = &mut region_pipeline {
+                    if let Some(vertexes) = state.report(regions_vertexes) {
+                        let updated = pipeline.update_vertexes(&display, vertexes);
+                        state.report(updated);
+                    }
+                }
+            }
             input.tick_reset();
             tick_system.end_tick(TICK_FRAME_ID);
             // tick_system.debug_tick(TICK_FRAME_ID);
@@ -235,15 +420,6 @@ fn main() {
                 _ => {}
             }
             input.update(&event);
-            if state.regenerate {
-                state.regenerate = false;
-                map = map.regenerate(state.seed, state.distance_fn, state.reshape_fn);
-                let regions_vertexes = extract_region_mesh(&map);
-                let (sites_vertexes, wires_vertexes) = setup_wires_and_sites_vertexes(&map);
-                region_pipeline.update_vertexes(&display, regions_vertexes);
-                site_pipeline.update_vertexes(&display, sites_vertexes);
-                wire_pipeline.update_vertexes(&display, wires_vertexes);
-            }
         }
     });
 }
@@ -3,6 +3,7 @@ use glium::{
     Display, DrawError, DrawParameters, Frame, Program, Surface, VertexBuffer,
 };
 
+use crate::error::ViewerError;
 use crate::{load_glsl, UniformStorage, VertexColor};
 
 pub struct SitePipeline {
@@ -12,23 +13,26 @@ pub struct SitePipeline {
 }
 
 impl SitePipeline {
-    pub fn new(sites: Vec<VertexColor>, display: &Display) -> Self {
-        let vertex_src = load_glsl("resources/shaders/voronoi_site.vs.glsl");
-        let frag_src = load_glsl("resources/shaders/voronoi_site.fs.glsl");
-        let program = glium::Program::from_source(display, &vertex_src, &frag_src, None).unwrap();
-        let vertexes_buffer = VertexBuffer::new(display, &sites)
-            .expect(&format!("failed to to build vertex buffer for sites"));
+    pub fn new(sites: Vec<VertexColor>, display: &Display) -> Result<Self, ViewerError> {
+        let vertex_src = load_glsl("resources/shaders/voronoi_site.vs.glsl")?;
+        let frag_src = load_glsl("resources/shaders/voronoi_site.fs.glsl")?;
+        let program = glium::Program::from_source(display, &vertex_src, &frag_src, None)?;
+        let vertexes_buffer = VertexBuffer::new(display, &sites)?;
         let indexes_buffer = NoIndices(PrimitiveType::Points);
-        Self {
+        Ok(Self {
             vertexes_buffer,
             indexes_buffer,
             program,
-        }
+        })
     }
 
-    pub fn update_vertexes(&mut self, display: &Display, data: Vec<VertexColor>) {
-        self.vertexes_buffer = VertexBuffer::new(display, &data)
-            .expect(&format!("failed to to build vertex buffer for sites"));
+    pub fn update_vertexes(
+        &mut self,
+        display: &Display,
+        data: Vec<VertexColor>,
+    ) -> Result<(), ViewerError> {
+        self.vertexes_buffer = VertexBuffer::new(display, &data)?;
+        Ok(())
     }
 
     pub fn draw(
@@ -54,23 +58,26 @@ pub struct WirePipeline {
 }
 
 impl WirePipeline {
-    pub fn new(wires: Vec<VertexColor>, display: &Display) -> Self {
-        let vertex_src = load_glsl("resources/shaders/voronoi_wire.vs.glsl");
-        let frag_src = load_glsl("resources/shaders/voronoi_wire.fs.glsl");
-        let program = glium::Program::from_source(display, &vertex_src, &frag_src, None).unwrap();
-        let vertexes_buffer = VertexBuffer::new(display, &wires)
-            .expect(&format!("failed to to build vertex buffer for wires"));
+    pub fn new(wires: Vec<VertexColor>, display: &Display) -> Result<Self, ViewerError> {
+        let vertex_src = load_glsl("resources/shaders/voronoi_wire.vs.glsl")?;
+        let frag_src = load_glsl("resources/shaders/voronoi_wire.fs.glsl")?;
+        let program = glium::Program::from_source(display, &vertex_src, &frag_src, None)?;
+        let vertexes_buffer = VertexBuffer::new(display, &wires)?;
         let indexes_buffer = NoIndices(PrimitiveType::LinesList);
-        Self {
+        Ok(Self {
             vertexes_buffer,
             indexes_buffer,
             program,
-        }
+        })
     }
 
-    pub fn update_vertexes(&mut self, display: &Display, data: Vec<VertexColor>) {
-        self.vertexes_buffer = VertexBuffer::new(display, &data)
-            .expect(&format!("failed to to build vertex buffer for wires"));
+    pub fn update_vertexes(
+        &mut self,
+        display: &Display,
+        data: Vec<VertexColor>,
+    ) -> Result<(), ViewerError> {
+        self.vertexes_buffer = VertexBuffer::new(display, &data)?;
+        Ok(())
     }
 
     pub fn draw(
@@ -95,23 +102,26 @@ pub struct RegionPipeline {
 }
 
 impl RegionPipeline {
-    pub fn new(regions: Vec<VertexColor>, display: &Display) -> Self {
-        let vertex_src = load_glsl("resources/shaders/map.vs.glsl");
-        let frag_src = load_glsl("resources/shaders/map.fs.glsl");
-        let program = glium::Program::from_source(display, &vertex_src, &frag_src, None).unwrap();
-        let vertexes_buffer = VertexBuffer::new(display, &regions)
-            .expect(&format!("failed to to build vertex buffer for regions"));
+    pub fn new(regions: Vec<VertexColor>, display: &Display) -> Result<Self, ViewerError> {
+        let vertex_src = load_glsl("resources/shaders/map.vs.glsl")?;
+        let frag_src = load_glsl("resources/shaders/map.fs.glsl")?;
+        let program = glium::Program::from_source(display, &vertex_src, &frag_src, None)?;
+        let vertexes_buffer = VertexBuffer::new(display, &regions)?;
         let indexes_buffer = NoIndices(PrimitiveType::TrianglesList);
-        Self {
+        Ok(Self {
             vertexes_buffer,
             indexes_buffer,
             program,
-        }
+        })
     }
 
-    pub fn update_vertexes(&mut self, display: &Display, data: Vec<VertexColor>) {
-        self.vertexes_buffer = VertexBuffer::new(display, &data)
-            .expect(&format!("failed to to build vertex buffer for regions"));
+    pub fn update_vertexes(
+        &mut self,
+        display: &Display,
+        data: Vec<VertexColor>,
+    ) -> Result<(), ViewerError> {
+        self.vertexes_buffer = VertexBuffer::new(display, &data)?;
+        Ok(())
     }
 
     pub fn draw(
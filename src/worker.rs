@@ -0,0 +1,100 @@
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use math::error::MapError;
+use math::map::{generate_map_with_progress, Map, MapSettings};
+use math::progress::{CancellationToken, Progress};
+
+type Outcome = (u64, Result<Map, MapError>);
+
+/// Regenerates maps on a background thread so the window keeps drawing the current one.
+pub struct MapWorker {
+    sender: Sender<Outcome>,
+    receiver: Receiver<Outcome>,
+    /// Id of the latest request, older results are dropped.
+    latest: u64,
+    /// Settings of the running request.
+    requested: Option<MapSettings>,
+    token: CancellationToken,
+    progress: Arc<Mutex<Option<Progress>>>,
+}
+
+impl Default for MapWorker {
+    fn default() -> Self {
+        let (sender, receiver) = channel();
+        Self {
+            sender,
+            receiver,
+            latest: 0,
+            requested: None,
+            token: CancellationToken::new(),
+            progress: Arc::default(),
+        }
+    }
+}
+
+impl MapWorker {
+    /// Starts regenerating `map` with `settings`, or generating from scratch without a map. The
+    /// request still running is cancelled.
+    pub fn request(&mut self, map: Option<&Arc<Map>>, settings: MapSettings) {
+        self.token.cancel();
+        self.latest += 1;
+        self.requested = Some(settings.clone());
+        self.token = CancellationToken::new();
+        self.progress = Arc::default();
+
+        let (id, map, token) = (self.latest, map.cloned(), self.token.clone());
+        let (sender, progress) = (self.sender.clone(), Arc::clone(&self.progress));
+        thread::spawn(move || {
+            let observer = |report: &Progress| *progress.lock().unwrap() = Some(*report);
+            let result = match map {
+                Some(map) => map.regenerate_with_progress(settings, &observer, &token),
+                None => generate_map_with_progress(settings, &observer, &token),
+            };
+            // the worker is gone when the window closed
+            let _ = sender.send((id, result));
+        });
+    }
+
+    /// Stops the running request, `poll` then returns `MapError::Cancelled` for it.
+    pub fn cancel(&self) {
+        self.token.cancel();
+    }
+
+    /// Stops the running request and drops its result.
+    pub fn discard(&mut self) {
+        self.token.cancel();
+        self.latest += 1;
+        self.requested = None;
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.requested.is_some()
+    }
+
+    /// Settings the shown map is about to be replaced with.
+    pub fn requested(&self) -> Option<&MapSettings> {
+        self.requested.as_ref()
+    }
+
+    /// Latest report of the running request.
+    pub fn progress(&self) -> Option<Progress> {
+        if self.is_running() {
+            *self.progress.lock().unwrap()
+        } else {
+            None
+        }
+    }
+
+    /// The map of the latest request once it's done, or why it failed.
+    pub fn poll(&mut self) -> Option<Result<Map, MapError>> {
+        while let Ok((id, result)) = self.receiver.try_recv() {
+            if id == self.latest {
+                self.requested = None;
+                return Some(result);
+            }
+        }
+        None
+    }
+}